@@ -0,0 +1,538 @@
+//! Browse archives (zip and tar, optionally compressed) like directories
+
+extern crate zip;
+extern crate tar;
+extern crate flate2;
+extern crate xz2;
+extern crate zstd;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::error::Error;
+use std::path::{Path, PathBuf, Component};
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+use std::os::unix::fs::PermissionsExt;
+
+use mcore::xdg;
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::{FileBrowserEntry, format_size};
+use actions::utils::{self, open};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+const SUFFIXES: &[(&str, ArchiveFormat)] = &[
+    (".zip", ArchiveFormat::Zip),
+    (".jar", ArchiveFormat::Zip),
+    (".tar", ArchiveFormat::Tar),
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tar.xz", ArchiveFormat::TarXz),
+    (".txz", ArchiveFormat::TarXz),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tzst", ArchiveFormat::TarZst),
+];
+
+/// Detect format by file name, return format and file name without suffix
+fn detect(path: &Path) -> Option<(ArchiveFormat, String)> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return None,
+    };
+    let lower = name.to_lowercase();
+    SUFFIXES.iter()
+        .find(|&&(suffix, _)| lower.ends_with(suffix) && lower.len() > suffix.len())
+        .map(|&(suffix, format)| (format, name[..name.len() - suffix.len()].to_string()))
+}
+
+/// A file or directory in archive
+#[derive(Debug, Clone)]
+struct ArchiveEntry {
+    /// Path inside archive, separated by '/', without leading or trailing '/'
+    path: String,
+    is_dir: bool,
+    /// For directories, total size of files inside
+    size: u64,
+}
+
+fn basename(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(pos) => &path[pos + 1..],
+        None => path,
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(pos) => &path[..pos],
+        None => "",
+    }
+}
+
+/// Normalize path inside archive, None if it's unsafe to extract (absolute or containing "..")
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(x) => parts.push(x.to_string_lossy().into_owned()),
+            Component::CurDir => {},
+            _ => return None,
+        }
+    }
+    if parts.len() == 0 { None } else { Some(parts.join("/")) }
+}
+
+/// Whether `path` is `prefix` or inside it
+fn is_under(path: &str, prefix: &str) -> bool {
+    prefix.len() == 0 || path == prefix ||
+        (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+}
+
+fn open_tar(archive: &Path, format: ArchiveFormat) -> io::Result<tar::Archive<Box<Read>>> {
+    let file = File::open(archive)?;
+    let reader : Box<Read> = match format {
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveFormat::TarXz => Box::new(xz2::read::XzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::stream::read::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// Read all entries, including directories which are not explicitly stored
+fn read_entries(archive: &Path, format: ArchiveFormat)
+    -> Result<BTreeMap<String, ArchiveEntry>, Box<Error + Send + Sync>> {
+    let mut raw = Vec::new();
+    if format == ArchiveFormat::Zip {
+        let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+        for idx in 0..zip.len() {
+            let file = zip.by_index(idx)?;
+            raw.push((PathBuf::from(file.name()), file.is_dir(), file.size()));
+        }
+    } else {
+        let mut tar = open_tar(archive, format)?;
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let is_dir = entry.header().entry_type().is_dir();
+            raw.push((entry.path()?.into_owned(), is_dir, entry.header().size()?));
+        }
+    }
+
+    let mut ret = BTreeMap::new();
+    for (path, is_dir, size) in raw {
+        let path = match normalize(&path) {
+            Some(path) => path,
+            None => {
+                warn!("Skipping unsafe archive entry {:?}", path);
+                continue;
+            }
+        };
+        let size = if is_dir { 0 } else { size };
+        let mut dir = parent(&path).to_string();
+        while dir.len() > 0 {
+            let next = parent(&dir).to_string();
+            ret.entry(dir.clone())
+                .or_insert(ArchiveEntry { path: dir, is_dir: true, size: 0 })
+                .size += size;
+            dir = next;
+        }
+        ret.entry(path.clone())
+            .or_insert(ArchiveEntry { path: path, is_dir: is_dir, size: size });
+    }
+    Ok(ret)
+}
+
+type CachedEntries = Arc<BTreeMap<String, ArchiveEntry>>;
+
+lazy_static! {
+    /// Entries of last read archive, as reading compressed tar requires decompressing all of it
+    static ref CACHE: Mutex<Option<(PathBuf, SystemTime, CachedEntries)>> = Mutex::new(None);
+}
+
+fn get_entries(archive: &Path, format: ArchiveFormat) -> Result<CachedEntries, Box<Error + Send + Sync>> {
+    let mtime = fs::metadata(archive)?.modified()?;
+    let mut cache = CACHE.lock().unwrap();
+    if let Some((ref path, ref cached_mtime, ref entries)) = *cache {
+        if path == archive && *cached_mtime == mtime {
+            return Ok(entries.clone());
+        }
+    }
+    debug!("Reading archive {:?}", archive);
+    let entries = Arc::new(read_entries(archive, format)?);
+    *cache = Some((archive.to_path_buf(), mtime, entries.clone()));
+    Ok(entries)
+}
+
+/// Make sure `path` under `target` is not written through symlinks,
+/// which may be planted by previous entries of a malicious archive
+fn check_symlinks(target: &Path, path: &Path) -> Result<(), Box<Error + Send + Sync>> {
+    let check = |path: &Path| -> Result<(), Box<Error + Send + Sync>> {
+        match fs::symlink_metadata(path) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() =>
+                Err(Box::new(ActionError::new(&format!("Refusing to write through symlink {:?}", path)))),
+            _ => Ok(()),
+        }
+    };
+    let mut current = target.to_path_buf();
+    check(&current)?;
+    for component in path.strip_prefix(target)?.components() {
+        current.push(component);
+        check(&current)?;
+    }
+    Ok(())
+}
+
+/// Extract `prefix` (all if empty) in archive to `target`
+fn extract(archive: &Path, format: ArchiveFormat, prefix: &str, target: &Path)
+    -> Result<(), Box<Error + Send + Sync>> {
+    info!("Extract {:?}:{} to {:?}", archive, prefix, target);
+    let target_of = |path: &str| -> PathBuf {
+        let rel = path[prefix.len()..].trim_left_matches('/');
+        if rel.len() == 0 { target.to_path_buf() } else { target.join(rel) }
+    };
+    fs::create_dir_all(target.parent().unwrap_or(Path::new("/")))?;
+
+    if format == ArchiveFormat::Zip {
+        let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+        for idx in 0..zip.len() {
+            let mut file = zip.by_index(idx)?;
+            let path = match normalize(Path::new(file.name())) {
+                Some(ref path) if is_under(path, prefix) => target_of(path),
+                _ => continue,
+            };
+            check_symlinks(target, &path)?;
+            if file.is_dir() {
+                fs::create_dir_all(&path)?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = File::create(&path)?;
+            io::copy(&mut file, &mut output)?;
+            if let Some(mode) = file.unix_mode() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+            }
+        }
+    } else {
+        let mut tar = open_tar(archive, format)?;
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let path = match normalize(&entry.path()?) {
+                Some(ref path) if is_under(path, prefix) => target_of(path),
+                _ => continue,
+            };
+            // hard links are resolved outside of target
+            if entry.header().entry_type().is_hard_link() {
+                warn!("Skipping hard link {:?}", path);
+                continue;
+            }
+            check_symlinks(target, &path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&path)?;
+        }
+    }
+
+    if fs::symlink_metadata(target).is_err() {
+        // directories without any entry inside
+        fs::create_dir_all(target)?;
+    }
+    Ok(())
+}
+
+/// A directory in archive, the root if `prefix` is empty
+struct ArchiveDirAction {
+    archive: PathBuf,
+    format: ArchiveFormat,
+    prefix: String,
+    size: u64,
+}
+
+/// A file in archive
+struct ArchiveFileAction {
+    archive: PathBuf,
+    format: ArchiveFormat,
+    entry: ArchiveEntry,
+}
+
+/// Extract a subtree to chosen directory
+struct ExtractAction {
+    archive: PathBuf,
+    format: ArchiveFormat,
+    prefix: String,
+}
+
+/// Open the archive itself with default application
+struct OpenArchiveAction {
+    archive: PathBuf,
+}
+
+fn path_item(path: &Path) -> Result<Item, Box<Error + Send + Sync>> {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    };
+    match FileBrowserEntry::new(name, path.to_path_buf()) {
+        Some(entry) => Ok(Item::new_action_item(Arc::new(Box::new(entry)))),
+        None => Err(Box::new(ActionError::new(&format!("Invalid path: {:?}", path)))),
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    detect(path).is_some()
+}
+
+/// List root of archive
+pub fn list(archive: &Path) -> ActionResult {
+    let format = match detect(archive) {
+        Some((format, _)) => format,
+        None => return Err(Box::new(ActionError::new(&format!("Unsupported archive: {:?}", archive)))),
+    };
+    ArchiveDirAction {
+        archive: archive.to_path_buf(),
+        format: format,
+        prefix: String::new(),
+        size: 0,
+    }.run()
+}
+
+impl Action for ArchiveDirAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(basename(&self.prefix));
+        item.subtitle = Some(format!("{}  {}:/{}", format_size(self.size),
+                                     self.archive.to_string_lossy(), self.prefix));
+        item.badge = Some("Archived Directory".into());
+        item.icon = Some(Icon::Character{ch: '\u{f115}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let entries = get_entries(&self.archive, self.format)?;
+        let mut children : Vec<&ArchiveEntry> = entries.values()
+            .filter(|x| parent(&x.path) == self.prefix.as_str())
+            .collect();
+        children.sort_by(|a, b| b.is_dir.cmp(&a.is_dir)
+                         .then(a.path.to_lowercase().cmp(&b.path.to_lowercase())));
+
+        let mut ret : Vec<Item> = children.into_iter().enumerate().map(|(idx, entry)| {
+            let mut item = if entry.is_dir {
+                Item::new_action_item(Arc::new(Box::new(ArchiveDirAction {
+                    archive: self.archive.clone(),
+                    format: self.format,
+                    prefix: entry.path.clone(),
+                    size: entry.size,
+                })))
+            } else {
+                Item::new_action_item(Arc::new(Box::new(ArchiveFileAction {
+                    archive: self.archive.clone(),
+                    format: self.format,
+                    entry: entry.clone(),
+                })))
+            };
+            item.priority = idx as i32;
+            item
+        }).collect();
+
+        let mut extract = Item::new_action_item(Arc::new(Box::new(ExtractAction {
+            archive: self.archive.clone(),
+            format: self.format,
+            prefix: self.prefix.clone(),
+        })));
+        extract.priority = -90;
+        ret.push(extract);
+
+        let mut up = if self.prefix.len() == 0 {
+            let mut open = Item::new_action_item(Arc::new(Box::new(OpenArchiveAction {
+                archive: self.archive.clone(),
+            })));
+            open.priority = -80;
+            ret.push(open);
+            path_item(self.archive.parent().unwrap_or(Path::new("/")))?
+        } else {
+            let prefix = parent(&self.prefix).to_string();
+            let size = entries.get(&prefix).map(|x| x.size).unwrap_or(0);
+            Item::new_action_item(Arc::new(Box::new(ArchiveDirAction {
+                archive: self.archive.clone(),
+                format: self.format,
+                prefix: prefix,
+                size: size,
+            })))
+        };
+        up.title = "..".into();
+        up.priority = -100;
+        ret.push(up);
+
+        Ok(ret)
+    }
+}
+
+impl Action for ArchiveFileAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(basename(&self.entry.path));
+        item.subtitle = Some(format!("{}  {}:/{}", format_size(self.entry.size),
+                                     self.archive.to_string_lossy(), self.entry.path));
+        item.badge = Some("Archived File".into());
+        item.icon = Some(Icon::Character{ch: '\u{f1c6}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    /// Extract to runtime directory (one per archive), return the extracted file
+    fn run(&self) -> ActionResult {
+        let mut hasher = DefaultHasher::new();
+        self.archive.hash(&mut hasher);
+        let dir = xdg::runtime_dir().join("minions-archive").join(format!("{:x}", hasher.finish()));
+        let target = dir.join(&self.entry.path);
+        if fs::symlink_metadata(&target).is_ok() {
+            fs::remove_file(&target)?;
+        }
+        extract(&self.archive, self.format, &self.entry.path, &target)?;
+        Ok(vec![path_item(&target)?])
+    }
+}
+
+impl ExtractAction {
+    /// Name of the extracted file or directory
+    fn name(&self) -> String {
+        if self.prefix.len() > 0 {
+            return basename(&self.prefix).into();
+        }
+        match detect(&self.archive) {
+            Some((_, stem)) => stem,
+            None => "archive".into(),
+        }
+    }
+
+    fn extract_to(&self, dir: &Path) -> ActionResult {
+        if !dir.is_dir() {
+            return Err(Box::new(ActionError::new(&format!("Not a directory: {:?}", dir))));
+        }
+        let target = dir.join(self.name());
+        if fs::symlink_metadata(&target).is_ok() {
+            return Err(Box::new(ActionError::new(&format!("File already exists: {:?}", target))));
+        }
+        extract(&self.archive, self.format, &self.prefix, &target)?;
+        Ok(vec![path_item(&target)?])
+    }
+}
+
+impl Action for ExtractAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(&format!("Extract {} to…", self.name()));
+        item.subtitle = Some(format!("Enter destination directory, or leave empty for {}",
+                                     self.archive.parent().unwrap_or(Path::new("/")).to_string_lossy()));
+        item.icon = Some(Icon::Character{ch: '\u{f019}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+    fn accept_text(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        self.extract_to(self.archive.parent().unwrap_or(Path::new("/")))
+    }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        self.extract_to(&utils::expand_path(text.trim()))
+    }
+}
+
+impl Action for OpenArchiveAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open with Default Application");
+        item.subtitle = Some(self.archive.to_string_lossy().into());
+        item.icon = Some(Icon::Character{ch: '\u{f08e}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        info!("open: {:?}", self.archive);
+        open::that(&self.archive.to_string_lossy())?;
+        Ok(Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tar;
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::path::Path;
+    use actions::archive::{detect, normalize, is_under, extract, ArchiveFormat};
+
+    #[test]
+    fn detect_test() {
+        assert_eq!(detect(Path::new("/tmp/foo.tar.gz")), Some((ArchiveFormat::TarGz, "foo".into())));
+        assert_eq!(detect(Path::new("Foo.ZIP")), Some((ArchiveFormat::Zip, "Foo".into())));
+        assert_eq!(detect(Path::new("foo.tar.zst")), Some((ArchiveFormat::TarZst, "foo".into())));
+        assert_eq!(detect(Path::new("foo.gz")), None);
+        assert_eq!(detect(Path::new(".tar")), None);
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(normalize(Path::new("./a/b/")), Some("a/b".into()));
+        assert_eq!(normalize(Path::new("/etc/passwd")), None);
+        assert_eq!(normalize(Path::new("a/../../b")), None);
+        assert!(is_under("a/b", "a"));
+        assert!(is_under("a", "a"));
+        assert!(!is_under("ab", "a"));
+        assert!(is_under("x", ""));
+    }
+
+    #[test]
+    fn extract_symlink_test() {
+        let dir = env::temp_dir().join("minions-archive-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("outside")).unwrap();
+
+        // "a/link" points outside, then "a/link/evil" is written through it
+        let archive = dir.join("evil.tar");
+        {
+            let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+            let mut header = tar::Header::new_gnu();
+            header.set_path("a/link").unwrap();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_link_name(dir.join("outside")).unwrap();
+            header.set_size(0);
+            header.set_cksum();
+            builder.append(&header, io::empty()).unwrap();
+
+            let mut header = tar::Header::new_gnu();
+            header.set_path("a/link/evil").unwrap();
+            header.set_size(4);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, "evil".as_bytes()).unwrap();
+            builder.finish().unwrap();
+        }
+
+        assert!(extract(&archive, ArchiveFormat::Tar, "", &dir.join("extracted")).is_err());
+        assert!(!dir.join("outside/evil").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
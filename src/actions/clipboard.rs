@@ -5,54 +5,213 @@
 * @Last Modified time: 2017-07-16
 */
 
-extern crate gtk;
-extern crate glib;
-extern crate gdk;
-extern crate gtk_sys;
-extern crate libc;
+//! Clipboard history
+
 extern crate chrono;
 
-use self::glib::signal::connect;
-use self::glib::translate::*;
-use self::gtk::Clipboard;
-use self::chrono::{Local, DateTime, TimeZone};
+use self::chrono::{Local, TimeZone};
+
+use toml;
 
-use std::sync::{Arc, Mutex};
-use std::mem::transmute;
-use std::collections::VecDeque;
-use std::time::SystemTime;
+use std::thread;
+use std::error::Error;
+use std::sync::{mpsc, Arc, Mutex};
+use std::path::{Path, PathBuf};
 
 use actions::ActionError;
-use mcore::action::{Action, ActionResult};
-use mcore::item::{Item, Icon};
+use actions::clipboard_store::{self, ClipboardStore, Entry};
+use actions::utils::clipboard_backend::{self, ClipboardBackend};
+use actions::utils::paste;
+use mcore::action::{Action, ActionArg, ActionResult};
+use mcore::item::{Item, ItemData, Icon};
+
+/// Non-text targets to record, besides text
+fn rich_targets(targets: &[String]) -> Vec<String> {
+    let mut ret = Vec::new();
+    if targets.iter().any(|x| x == "text/html") {
+        ret.push("text/html".into());
+    }
+    // GTK offers image/png for any image it owns, other applications may not
+    if targets.iter().any(|x| x == "image/png") {
+        ret.push("image/png".into());
+    } else if let Some(target) = targets.iter().find(|x| x.starts_with("image/")) {
+        ret.push(target.clone());
+    }
+    ret
+}
+
+pub struct ClipboardHistoryAction {
+    store: Arc<Mutex<ClipboardStore>>,
+}
+
+/// Copy history entry back, with all its targets
+struct ClipboardEntryAction {
+    store: Arc<Mutex<ClipboardStore>>,
+    entry: Entry,
+    thumbnail: Option<PathBuf>,
+    /// Image file to quick-send, if not encrypted
+    image_file: Option<PathBuf>,
+    marked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryOperation {
+    /// Toggle pinned
+    Pin,
+    Delete,
+    /// Toggle marked for joining
+    Mark,
+}
+
+/// Operation on history entry, accepting quick-sent history items only
+struct EntryOperationAction {
+    store: Arc<Mutex<ClipboardStore>>,
+    op: EntryOperation,
+}
 
-unsafe extern "C" fn trampoline(clipboard: *mut gtk_sys::GtkClipboard,
-                                _: *mut libc::c_void,
-                                f: &Box<Fn(&Clipboard) + 'static>) {
-    f(&Clipboard::from_glib_none(clipboard))
+/// Join marked entries with separator, copy the result
+struct JoinMarkedAction {
+    store: Arc<Mutex<ClipboardStore>>,
+    count: usize,
 }
 
+/// Remove all entries, after confirmation
+struct ClearHistoryAction {
+    store: Arc<Mutex<ClipboardStore>>,
+    confirmed: bool,
+}
 
-fn connect_clipboard_change<F>(clipboard: &Clipboard, f: F)
-where F: Fn(&Clipboard) + 'static {
-    unsafe {
-        let f: Box<Box<Fn(&Clipboard) + 'static>> =
-            Box::new(Box::new(f));
-        connect(clipboard.to_glib_none().0, "owner-change",
-                transmute(trampoline as usize), Box::into_raw(f) as *mut _);
+fn get_backend() -> Result<Arc<Box<ClipboardBackend>>, Box<Error + Send + Sync>> {
+    match clipboard_backend::get() {
+        Some(backend) => Ok(backend),
+        None => Err(Box::new(ActionError::new("No clipboard available"))),
     }
 }
 
+/// Items of history entries, pinned first
+fn history_items(store: &Arc<Mutex<ClipboardStore>>) -> ActionResult {
+    let mut locked = match store.lock() {
+        Ok(locked) => locked,
+        Err(_) => return Err(Box::new(ActionError::new("Unable to unlock history"))),
+    };
+    let mut history : Vec<Entry> = locked.entries().iter().cloned().collect();
+    debug!("Returning {} clipboard histories", history.len());
+    if history.len() == 0 {
+        return Err(Box::new(ActionError::new("No clipboard history available")));
+    }
+    history.sort_by_key(|x| !x.pinned);
+    let items = history.into_iter().enumerate().map(|(idx, entry)| {
+        let thumbnail = locked.thumbnail(&entry);
+        let image_file = locked.image_file(&entry);
+        let marked = locked.is_marked(&entry);
+        let mut item = Item::new_action_item(Arc::new(Box::new(ClipboardEntryAction {
+            store: store.clone(),
+            entry: entry,
+            thumbnail: thumbnail,
+            image_file: image_file,
+            marked: marked,
+        })));
+        item.priority = idx as i32;
+        item
+    }).collect();
+    Ok(items)
+}
 
-pub struct ClipboardHistoryAction {
-    history_max_len: usize,
-    history: Arc<Mutex<VecDeque<(String, DateTime<Local>)>>>,
+/// Items of history entries, with operations on them
+fn list_history(store: &Arc<Mutex<ClipboardStore>>) -> ActionResult {
+    let mut ret = history_items(store)?;
+    let count = ret.len();
+    let locked = match store.lock() {
+        Ok(locked) => locked,
+        Err(_) => return Err(Box::new(ActionError::new("Unable to unlock history"))),
+    };
+    let marked_count = locked.marked_texts().len();
+    if marked_count > 0 {
+        let mut item = Item::new_action_item(Arc::new(Box::new(JoinMarkedAction {
+            store: store.clone(),
+            count: marked_count,
+        })));
+        item.priority = -1;
+        ret.push(item);
+    }
+    let mut item = Item::new_action_item(Arc::new(Box::new(ClearHistoryAction {
+        store: store.clone(),
+        confirmed: false,
+    })));
+    item.priority = count as i32;
+    ret.push(item);
+    Ok(ret)
+}
+
+/// Expand `\n` and `\t` in separator
+fn unescape_separator(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+impl Action for ClipboardEntryAction {
+    fn get_item(&self) -> Item {
+        let mut item = if self.entry.text.len() > 0 {
+            Item::new_text_item(&self.entry.text)
+        } else {
+            Item::new(&format!("Image ({})", self.entry.image().map(|x| x.target.as_str()).unwrap_or("unknown")))
+        };
+        let mut subtitle = format!("{}{}, {} bytes",
+                                   if self.entry.pinned { "Pinned, " } else { "" },
+                                   Local.timestamp(self.entry.time, 0).format("%T %b %e").to_string(),
+                                   self.entry.size());
+        if let Some(ref source) = self.entry.source {
+            subtitle += &format!(", from {}", source);
+        }
+        item.subtitle = Some(subtitle);
+        item.badge = if self.marked {
+            Some("Marked".into())
+        } else if self.entry.image().is_some() {
+            Some("Image".into())
+        } else if self.entry.blobs.len() > 0 {
+            Some("Rich Text".into())
+        } else {
+            None
+        };
+        item.icon = Some(match self.thumbnail {
+            Some(ref path) => Icon::File(path.clone()),
+            None if self.entry.pinned => Icon::Character{ch: '\u{f08d}', font: "FontAwesome".into()},
+            None => Icon::Character{ch: '\u{f0ea}', font: "FontAwesome".into()},
+        });
+        // images are quick-sent as files
+        if self.entry.text.len() == 0 {
+            item.data = self.image_file.clone().map(ItemData::Path);
+        }
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        let mut contents = if self.entry.text.len() > 0 {
+            clipboard_backend::text_contents(&self.entry.text)
+        } else {
+            Vec::new()
+        };
+        contents.extend(self.store.lock().unwrap().blobs(&self.entry));
+        if contents.len() == 0 {
+            return Err(Box::new(ActionError::new("Clipboard history content is lost")));
+        }
+        get_backend()?.set_contents(contents)?;
+        // rich content can only be pasted by Ctrl+V
+        if paste::method().is_some() {
+            paste::paste(None)?;
+        }
+        Ok(Vec::new())
+    }
 }
 
 impl Action for ClipboardHistoryAction {
     fn get_item(&self) -> Item {
+        let max_entries = self.store.lock().unwrap().max_entries();
         let mut item = Item::new("Clipboard History");
-        item.subtitle = Some(format!("View clipboard history up to {} entries", self.history_max_len));
+        item.subtitle = Some(format!("View clipboard history up to {} entries", max_entries));
         item.icon = Some(Icon::Character{ch: '', font: "FontAwesome".into()});
         item
     }
@@ -60,57 +219,209 @@ impl Action for ClipboardHistoryAction {
     fn accept_nothing(&self) -> bool { true }
 
     fn run(&self) -> ActionResult {
-        if let Ok(history) = self.history.lock() {
-            debug!("Returning {} clipboard histories", history.len());
-            if history.len() == 0 {
-                Err(Box::new(ActionError::new("No clipboard history available")))
-            } else {
-                Ok(history.iter().map(|x| {
-                    let mut item = Item::new_text_item(&x.0);
-                    item.subtitle = Some(format!("{}, {} bytes",
-                                                 x.1.format("%T %b %e").to_string(),
-                                                 x.0.len()));
-                    item.icon = Some(Icon::Character{ch: '', font: "FontAwesome".into()});
-                    item
-                }).collect())
+        list_history(&self.store)
+    }
+
+    fn is_search_provider(&self) -> bool { true }
+
+    fn search(&self, _: &str) -> ActionResult {
+        history_items(&self.store)
+    }
+}
+
+impl EntryOperationAction {
+    /// Id of history entry sent as `arg`
+    fn find(&self, arg: &ActionArg) -> Option<String> {
+        let store = match self.store.lock() {
+            Ok(store) => store,
+            Err(_) => return None,
+        };
+        match *arg {
+            ActionArg::Text(ref text) => store.find_text(text),
+            ActionArg::Path(ref path) => store.find_image(path),
+            ActionArg::None => None,
+        }
+    }
+
+    fn apply(&self, arg: &ActionArg) -> ActionResult {
+        let id = match self.find(arg) {
+            Some(id) => id,
+            None => return Err(Box::new(ActionError::new("Not a clipboard history entry"))),
+        };
+        {
+            let mut store = self.store.lock().unwrap();
+            match self.op {
+                EntryOperation::Pin => store.toggle_pin(&id),
+                EntryOperation::Delete => store.remove(&id),
+                EntryOperation::Mark => store.toggle_mark(&id),
+            };
+        }
+        list_history(&self.store)
+    }
+}
+
+impl Action for EntryOperationAction {
+    fn get_item(&self) -> Item {
+        let (title, ch) = match self.op {
+            EntryOperation::Pin => ("Pin or Unpin in Clipboard History", '\u{f08d}'),
+            EntryOperation::Delete => ("Delete from Clipboard History", '\u{f1f8}'),
+            EntryOperation::Mark => ("Mark or Unmark for Joining", '\u{f046}'),
+        };
+        let mut item = Item::new(title);
+        item.subtitle = Some(match self.op {
+            EntryOperation::Pin => "Pinned entries are never evicted and shown first",
+            EntryOperation::Delete => "Remove this entry from clipboard history",
+            EntryOperation::Mark => "Join marked entries from clipboard history later",
+        }.into());
+        item.badge = Some("Clipboard".into());
+        item.icon = Some(Icon::Character{ch: ch, font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_arg(&self, arg: &ActionArg) -> bool {
+        self.find(arg).is_some()
+    }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        self.apply(&ActionArg::Text(text.into()))
+    }
+
+    fn run_path(&self, path: &Path) -> ActionResult {
+        self.apply(&ActionArg::Path(path.to_path_buf()))
+    }
+}
+
+impl Action for JoinMarkedAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(&format!("Join {} Marked Entries", self.count));
+        item.subtitle = Some("Copy marked entries joined by newline, or by input separator".into());
+        item.icon = Some(Icon::Character{ch: '\u{f0c1}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+    fn accept_text(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        self.run_text("\\n")
+    }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        let joined = {
+            let mut store = self.store.lock().unwrap();
+            let texts = store.marked_texts();
+            if texts.len() == 0 {
+                return Err(Box::new(ActionError::new("No marked clipboard entry")));
             }
-        } else {
-            Err(Box::new(ActionError::new("Unable to unlock history")))
+            store.clear_marks();
+            texts.join(&unescape_separator(text))
+        };
+        get_backend()?.set_text(&joined, false)?;
+        Ok(Vec::new())
+    }
+}
+
+impl Action for ClearHistoryAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(if self.confirmed { "Confirm Clearing Clipboard History" } else { "Clear Clipboard History" });
+        item.subtitle = Some("Remove all entries, including pinned ones".into());
+        item.icon = Some(Icon::Character{ch: '\u{f1f8}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { !self.confirmed }
+
+    fn run(&self) -> ActionResult {
+        if !self.confirmed {
+            return Ok(vec![Item::new_action_item(Arc::new(Box::new(ClearHistoryAction {
+                store: self.store.clone(),
+                confirmed: true,
+            })))]);
         }
+        info!("Clearing clipboard history");
+        self.store.lock().unwrap().clear();
+        Ok(Vec::new())
     }
 }
 
 impl ClipboardHistoryAction {
-    pub fn new(history_max_len: usize) -> ClipboardHistoryAction {
+    /// History action, with operations on history entries
+    pub fn get_all(config: toml::Value) -> Vec<Box<Action + Sync + Send>> {
+        let action = match ClipboardHistoryAction::new(config) {
+            Some(action) => action,
+            None => return Vec::new(),
+        };
+        let mut ret : Vec<Box<Action + Sync + Send>> = vec![EntryOperation::Pin, EntryOperation::Delete, EntryOperation::Mark]
+            .into_iter()
+            .map(|op| -> Box<Action + Sync + Send> {
+                Box::new(EntryOperationAction { store: action.store.clone(), op: op })
+            })
+            .collect();
+        ret.insert(0, Box::new(action));
+        ret
+    }
+
+    fn new(config: toml::Value) -> Option<ClipboardHistoryAction> {
+        let store = match ClipboardStore::new(config) {
+            Ok(store) => store,
+            Err(error) => {
+                warn!("Unable to load clipboard history: {}", error);
+                return None;
+            }
+        };
+        let backend = match clipboard_backend::get() {
+            Some(backend) => backend,
+            None => {
+                warn!("No clipboard available, clipboard history disabled");
+                return None;
+            }
+        };
         let action = ClipboardHistoryAction {
-            history_max_len: history_max_len,
-            history: Arc::new(Mutex::new(VecDeque::new())),
+            store: Arc::new(Mutex::new(store)),
         };
-        let history = action.history.clone();
-
-        let clipboard = gtk::Clipboard::get(&gdk::Atom::intern("CLIPBOARD"));
-        connect_clipboard_change(&clipboard, move |clipboard| {
-            let content = clipboard.wait_for_text();
-            if let Some(text) = content {
-                trace!("New clipboard text: {:?}", text);
-                if let Ok(mut history) = history.lock() {
-                    let is_dup = if let Some(front) = history.front() {
-                        text == front.0.as_str()
-                    } else {
-                        false
-                    };
-                    if is_dup {
-                        debug!("Duplicate, do not push to history");
-                    } else {
-                        history.push_front((text.into(), Local::now()));
-                    }
-                    while history.len() > history_max_len {
-                        history.pop_back().unwrap();
-                    }
+
+        // looking up source application and saving are slow, do them out of the main loop
+        let (sender, receiver) = mpsc::channel::<(String, Vec<(String, Vec<u8>)>)>();
+        let store = action.store.clone();
+        thread::spawn(move || {
+            for (text, rich) in receiver.iter() {
+                let source = clipboard_store::active_application();
+                if let Ok(mut store) = store.lock() {
+                    store.push(&text, rich, source);
                 }
             }
         });
-        action
+
+        let watcher = backend.clone();
+        let result = backend.watch(Box::new(move || {
+            if watcher.is_sensitive() {
+                debug!("Sensitive clipboard content, do not push to history");
+                return;
+            }
+            let text = watcher.get_text(false).unwrap_or(String::new());
+            let targets = watcher.targets();
+            let rich : Vec<(String, Vec<u8>)> = rich_targets(&targets).into_iter()
+                .filter_map(|target| match watcher.get_contents(&target) {
+                    Some(ref data) if data.len() > 0 => Some((target, data.clone())),
+                    _ => None,
+                })
+                .collect();
+            if text.len() == 0 && rich.len() == 0 {
+                return;
+            }
+            trace!("New clipboard text: {:?}, with {:?}", text, rich.iter().map(|x| &x.0).collect::<Vec<_>>());
+            if let Err(error) = sender.send((text, rich)) {
+                warn!("Unable to push clipboard history: {}", error);
+            }
+        }));
+        if let Err(error) = result {
+            warn!("Unable to watch clipboard, clipboard history disabled: {}", error);
+            return None;
+        }
+        Some(action)
     }
 }
-
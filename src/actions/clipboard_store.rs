@@ -0,0 +1,642 @@
+//! Persistent storage of clipboard history, optionally encrypted.
+//! Images and rich text are stored as separate files named by their SHA-256
+
+extern crate crypto;
+extern crate regex;
+extern crate chrono;
+extern crate serde_json;
+
+use self::crypto::aes::{self, KeySize};
+use self::crypto::digest::Digest;
+use self::crypto::hmac::Hmac;
+use self::crypto::mac::Mac;
+use self::crypto::sha2::Sha256;
+use self::crypto::util::fixed_time_eq;
+use self::regex::Regex;
+use self::chrono::Local;
+
+use toml;
+
+use std::fs::{self, File, OpenOptions, DirBuilder};
+use std::io::prelude::*;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::collections::{VecDeque, HashSet};
+use std::os::unix::fs::{OpenOptionsExt, DirBuilderExt};
+
+use mcore::xdg;
+use actions::ActionError;
+use actions::utils::{self, thumbnail};
+
+/// Header of encrypted history file
+const ENCRYPTED_MAGIC: &[u8] = b"MINIONS-ENC1";
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+/// AES-256 key followed by HMAC-SHA256 key
+const KEY_LEN: usize = 64;
+/// Default storage budget of images and rich text, in bytes
+const DEFAULT_MAX_BLOB_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    pub max_entries: usize,
+    /// Drop entries older than this, in days
+    pub max_age_days: Option<u64>,
+    /// Drop oldest entries when total text size exceeds this, in bytes
+    pub max_size: Option<usize>,
+    /// Drop oldest images and rich text when their total size exceeds this, in bytes
+    pub max_blob_size: Option<usize>,
+    /// Do not record text matching any of these regexes
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Encrypt history file with key in `key_file`
+    #[serde(default)]
+    pub encrypt: bool,
+    /// Default to ~/.minions/clipboard.key, generated if not exists
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub text: String,
+    /// Unix timestamp
+    pub time: i64,
+    /// Application which owned the clipboard, if known
+    pub source: Option<String>,
+    /// Non-text targets, like image/png or text/html
+    #[serde(default)]
+    pub blobs: Vec<Blob>,
+    /// Pinned entries are never evicted
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blob {
+    pub target: String,
+    /// SHA-256 of content, also the file name
+    pub hash: String,
+    pub size: usize,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+impl Entry {
+    /// Identity of all targets, for de-duplication
+    pub fn id(&self) -> String {
+        let mut content = self.text.as_bytes().to_vec();
+        for blob in self.blobs.iter() {
+            content.push(0);
+            content.extend_from_slice(blob.target.as_bytes());
+            content.push(0);
+            content.extend_from_slice(blob.hash.as_bytes());
+        }
+        sha256_hex(&content)
+    }
+
+    pub fn image(&self) -> Option<&Blob> {
+        self.blobs.iter().find(|x| x.target.starts_with("image/"))
+    }
+
+    /// Total size of all targets, in bytes
+    pub fn size(&self) -> usize {
+        self.text.len() + self.blobs.iter().map(|x| x.size).sum::<usize>()
+    }
+}
+
+pub struct ClipboardStore {
+    config: Config,
+    exclude: Vec<Regex>,
+    key: Option<Vec<u8>>,
+    path: PathBuf,
+    blob_dir: PathBuf,
+    entries: VecDeque<Entry>,
+    /// Ids of entries marked for joining, in marking order
+    marked: Vec<String>,
+    /// Hashes of images whose thumbnails cannot be generated, not retried
+    no_thumbnail: HashSet<String>,
+}
+
+fn read_urandom(len: usize) -> Result<Vec<u8>, Box<Error + Send + Sync>> {
+    let mut ret = vec![0u8; len];
+    File::open("/dev/urandom")?.read_exact(&mut ret)?;
+    Ok(ret)
+}
+
+/// Write file atomically, readable only by user
+fn write_private(path: &PathBuf, data: &[u8]) -> Result<(), Box<Error + Send + Sync>> {
+    let tmp_file = path.with_extension("tmp");
+    {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true)
+            .mode(0o600).open(&tmp_file)?;
+        file.write_all(data)?;
+    }
+    fs::rename(&tmp_file, path)?;
+    Ok(())
+}
+
+fn create_private_dir(path: &PathBuf) -> Result<(), Box<Error + Send + Sync>> {
+    if !path.is_dir() {
+        DirBuilder::new().recursive(true).mode(0o700).create(path)?;
+    }
+    Ok(())
+}
+
+/// Load key from file, generate a new one if not exists
+fn load_key(path: &PathBuf) -> Result<Vec<u8>, Box<Error + Send + Sync>> {
+    if path.exists() {
+        let mut key = Vec::new();
+        File::open(path)?.read_to_end(&mut key)?;
+        if key.len() != KEY_LEN {
+            return Err(Box::new(ActionError::new(&format!("Invalid clipboard key file {:?}", path))));
+        }
+        Ok(key)
+    } else {
+        info!("Generating clipboard key at {:?}", path);
+        let key = read_urandom(KEY_LEN)?;
+        write_private(path, &key)?;
+        Ok(key)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), key);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+/// AES-256-CTR then HMAC-SHA256 over IV and ciphertext
+fn encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut cipher_text = vec![0u8; data.len()];
+    aes::ctr(KeySize::KeySize256, &key[..32], iv).process(data, &mut cipher_text);
+    let mut ret = ENCRYPTED_MAGIC.to_vec();
+    ret.extend_from_slice(iv);
+    ret.extend_from_slice(&cipher_text);
+    let mac = hmac(&key[32..], &ret[ENCRYPTED_MAGIC.len()..]);
+    ret.extend(mac);
+    ret
+}
+
+fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<Error + Send + Sync>> {
+    if data.len() < ENCRYPTED_MAGIC.len() + IV_LEN + MAC_LEN || !data.starts_with(ENCRYPTED_MAGIC) {
+        return Err(Box::new(ActionError::new("Invalid encrypted clipboard history")));
+    }
+    let body = &data[ENCRYPTED_MAGIC.len()..data.len() - MAC_LEN];
+    if !fixed_time_eq(&hmac(&key[32..], body), &data[data.len() - MAC_LEN..]) {
+        return Err(Box::new(ActionError::new("Clipboard history authentication failed")));
+    }
+    let (iv, cipher_text) = body.split_at(IV_LEN);
+    let mut ret = vec![0u8; cipher_text.len()];
+    aes::ctr(KeySize::KeySize256, &key[..32], iv).process(cipher_text, &mut ret);
+    Ok(ret)
+}
+
+/// Name of the application owning the active window, using xdotool
+pub fn active_application() -> Option<String> {
+    lazy_static! {
+        static ref HAS_XDOTOOL: bool = utils::command_exists("xdotool");
+    }
+    if !*HAS_XDOTOOL {
+        return None;
+    }
+    let output = match Command::new("xdotool").args(&["getactivewindow", "getwindowpid"]).output() {
+        Ok(ref output) if output.status.success() => output.stdout.clone(),
+        _ => return None,
+    };
+    let pid = String::from_utf8_lossy(&output).trim().to_string();
+    if pid.len() == 0 {
+        return None;
+    }
+    let mut comm = String::new();
+    match File::open(format!("/proc/{}/comm", pid)) {
+        Ok(mut file) => { let _ = file.read_to_string(&mut comm); },
+        Err(_) => return None,
+    }
+    let comm = comm.trim();
+    if comm.len() > 0 { Some(comm.to_string()) } else { None }
+}
+
+impl ClipboardStore {
+    pub fn new(config: toml::Value) -> Result<ClipboardStore, Box<Error + Send + Sync>> {
+        let config = config.try_into::<Config>()?;
+        let mut exclude = Vec::new();
+        for pattern in config.exclude.iter() {
+            match Regex::new(pattern) {
+                Ok(regex) => exclude.push(regex),
+                Err(error) => warn!("Invalid clipboard exclude pattern {}: {}", pattern, error),
+            }
+        }
+        let key = if config.encrypt {
+            let key_file = match config.key_file {
+                Some(ref path) => utils::expand_path(path),
+                None => utils::minions_dir().join("clipboard.key"),
+            };
+            Some(load_key(&key_file)?)
+        } else {
+            None
+        };
+        let mut store = ClipboardStore {
+            config: config,
+            exclude: exclude,
+            key: key,
+            path: utils::minions_dir().join("clipboard_history"),
+            blob_dir: utils::minions_dir().join("clipboard_blobs"),
+            entries: VecDeque::new(),
+            marked: Vec::new(),
+            no_thumbnail: HashSet::new(),
+        };
+        store.load();
+        Ok(store)
+    }
+
+    fn load(&mut self) {
+        let mut data = Vec::new();
+        match File::open(&self.path) {
+            Ok(mut file) => if let Err(error) = file.read_to_end(&mut data) {
+                warn!("Unable to read clipboard history: {}", error);
+                return;
+            },
+            Err(_) => return,
+        }
+        let data = match self.key {
+            Some(ref key) => match decrypt(key, &data) {
+                Ok(data) => data,
+                Err(error) => {
+                    warn!("Unable to decrypt clipboard history: {}", error);
+                    return;
+                }
+            },
+            None => data,
+        };
+        match serde_json::from_slice::<VecDeque<Entry>>(&data) {
+            Ok(entries) => {
+                self.entries = entries;
+                self.trim();
+                debug!("Loaded {} clipboard history entries", self.entries.len());
+            },
+            Err(error) => warn!("Invalid clipboard history file: {}", error),
+        }
+    }
+
+    pub fn save(&self) {
+        let result = serde_json::to_vec(&self.entries)
+            .map_err(|e| -> Box<Error + Send + Sync> { Box::new(e) })
+            .and_then(|data| {
+                let data = match self.key {
+                    Some(ref key) => encrypt(key, &read_urandom(IV_LEN)?, &data),
+                    None => data,
+                };
+                write_private(&self.path, &data)
+            });
+        if let Err(error) = result {
+            warn!("Unable to save clipboard history: {}", error);
+        }
+        self.collect_garbage();
+    }
+
+    /// Remove blob and thumbnail files not referenced by any entry,
+    /// and all thumbnails if encrypted
+    fn collect_garbage(&self) {
+        let referenced : HashSet<&str> = self.entries.iter()
+            .flat_map(|x| x.blobs.iter().map(|blob| blob.hash.as_str()))
+            .collect();
+        let files = fs::read_dir(&self.blob_dir).into_iter()
+            .chain(fs::read_dir(ClipboardStore::thumbnail_dir()).into_iter())
+            .flat_map(|x| x);
+        for file in files.filter_map(|x| x.ok()) {
+            let name = file.file_name().to_string_lossy().into_owned();
+            let is_thumbnail = name.ends_with(".png");
+            if (is_thumbnail && self.key.is_some()) || !referenced.contains(name.trim_right_matches(".png")) {
+                debug!("Removing unused clipboard blob {:?}", file.path());
+                if let Err(error) = fs::remove_file(file.path()) {
+                    warn!("Unable to remove {:?}: {}", file.path(), error);
+                }
+            }
+        }
+    }
+
+    fn write_blob(&self, hash: &str, data: &[u8]) -> Result<(), Box<Error + Send + Sync>> {
+        let path = self.blob_dir.join(hash);
+        if path.exists() {
+            return Ok(());
+        }
+        create_private_dir(&self.blob_dir)?;
+        match self.key {
+            Some(ref key) => write_private(&path, &encrypt(key, &read_urandom(IV_LEN)?, data)),
+            None => write_private(&path, data),
+        }
+    }
+
+    fn read_blob(&self, blob: &Blob) -> Result<Vec<u8>, Box<Error + Send + Sync>> {
+        let mut data = Vec::new();
+        File::open(self.blob_dir.join(&blob.hash))?.read_to_end(&mut data)?;
+        match self.key {
+            Some(ref key) => decrypt(key, &data),
+            None => Ok(data),
+        }
+    }
+
+    /// Contents of all non-text targets of `entry`, as (target, data)
+    pub fn blobs(&self, entry: &Entry) -> Vec<(String, Vec<u8>)> {
+        entry.blobs.iter().filter_map(|blob| match self.read_blob(blob) {
+            Ok(data) => Some((blob.target.clone(), data)),
+            Err(error) => {
+                warn!("Unable to read clipboard {} content: {}", blob.target, error);
+                None
+            }
+        }).collect()
+    }
+
+    fn thumbnail_dir() -> PathBuf {
+        xdg::runtime_dir().join("minions-clipboard")
+    }
+
+    fn thumbnail_path(hash: &str) -> PathBuf {
+        ClipboardStore::thumbnail_dir().join(format!("{}.png", hash))
+    }
+
+    fn write_thumbnail(&mut self, hash: &str, data: &[u8]) {
+        let result = create_private_dir(&ClipboardStore::thumbnail_dir())
+            .and_then(|_| thumbnail::generate(data, &ClipboardStore::thumbnail_path(hash)));
+        if let Err(error) = result {
+            warn!("Unable to write clipboard thumbnail: {}", error);
+            self.no_thumbnail.insert(hash.into());
+        }
+    }
+
+    /// Small image to show as icon of `entry`, generated when pushed.
+    /// Runtime directory may be cleared, in which case it's generated again.
+    /// Not available if encrypted, as thumbnails are stored in plain
+    pub fn thumbnail(&mut self, entry: &Entry) -> Option<PathBuf> {
+        if self.key.is_some() {
+            return None;
+        }
+        let blob = match entry.image() {
+            Some(blob) if !self.no_thumbnail.contains(&blob.hash) => blob,
+            _ => return None,
+        };
+        let path = ClipboardStore::thumbnail_path(&blob.hash);
+        if !path.exists() {
+            let data = self.read_blob(blob);
+            match data {
+                Ok(data) => self.write_thumbnail(&blob.hash, &data),
+                Err(error) => {
+                    warn!("Unable to read clipboard {} content: {}", blob.target, error);
+                    self.no_thumbnail.insert(blob.hash.clone());
+                },
+            }
+        }
+        if path.exists() { Some(path) } else { None }
+    }
+
+    /// Image file of `entry`, only if not encrypted
+    pub fn image_file(&self, entry: &Entry) -> Option<PathBuf> {
+        match (entry.image(), &self.key) {
+            (Some(blob), &None) => Some(self.blob_dir.join(&blob.hash)),
+            _ => None,
+        }
+    }
+
+    /// Remove entries exceeding count, age or size limit, except pinned ones
+    fn trim(&mut self) {
+        if let Some(days) = self.config.max_age_days {
+            let min_time = Local::now().timestamp() - (days * 86400) as i64;
+            self.entries.retain(|x| x.pinned || x.time >= min_time);
+        }
+        let max_entries = self.config.max_entries;
+        let mut count = 0;
+        self.entries.retain(|x| x.pinned || { count += 1; count <= max_entries });
+        if let Some(max_size) = self.config.max_size {
+            let mut size = 0;
+            self.entries.retain(|x| x.pinned || { size += x.text.len(); size <= max_size });
+        }
+        // strip older images and rich text exceeding budget, keeping their text.
+        // Pinned entries take their share first
+        let max_blob_size = self.config.max_blob_size.unwrap_or(DEFAULT_MAX_BLOB_SIZE);
+        let mut seen = HashSet::new();
+        let mut blob_size = 0;
+        for pinned in vec![true, false] {
+            for entry in self.entries.iter_mut().filter(|x| x.pinned == pinned) {
+                entry.blobs.retain(|x| {
+                    if seen.contains(&x.hash) {
+                        return true;
+                    }
+                    if !pinned && blob_size + x.size > max_blob_size {
+                        return false;
+                    }
+                    blob_size += x.size;
+                    seen.insert(x.hash.clone());
+                    true
+                });
+            }
+        }
+        self.entries.retain(|x| x.text.len() > 0 || x.blobs.len() > 0);
+        let ids : HashSet<String> = self.entries.iter().map(|x| x.id()).collect();
+        self.marked.retain(|x| ids.contains(x));
+    }
+
+    pub fn is_excluded(&self, text: &str) -> bool {
+        self.exclude.iter().any(|x| x.is_match(text))
+    }
+
+    /// Record new clipboard text along with other targets as (target, data),
+    /// return whether it's added. Same content recorded before is moved to front
+    pub fn push(&mut self, text: &str, rich: Vec<(String, Vec<u8>)>, source: Option<String>) -> bool {
+        if text.len() == 0 && rich.len() == 0 {
+            return false;
+        }
+        if self.is_excluded(text) {
+            debug!("Clipboard text excluded from history");
+            return false;
+        }
+        let mut blobs = Vec::new();
+        for (target, data) in rich.into_iter() {
+            let hash = sha256_hex(&data);
+            if let Err(error) = self.write_blob(&hash, &data) {
+                warn!("Unable to save clipboard {} content: {}", target, error);
+                continue;
+            }
+            if self.key.is_none() && target.starts_with("image/") &&
+                !ClipboardStore::thumbnail_path(&hash).exists() {
+                self.write_thumbnail(&hash, &data);
+            }
+            blobs.push(Blob { target: target, hash: hash, size: data.len() });
+        }
+        let mut entry = Entry {
+            text: text.into(),
+            time: Local::now().timestamp(),
+            source: source,
+            blobs: blobs,
+            pinned: false,
+        };
+        let hash = entry.id();
+        if self.entries.front().map(|x| x.id() == hash).unwrap_or(false) {
+            debug!("Duplicate, do not push to history");
+            return false;
+        }
+        entry.pinned = self.entries.iter().any(|x| x.pinned && x.id() == hash);
+        self.entries.retain(|x| x.id() != hash);
+        self.entries.push_front(entry);
+        self.trim();
+        self.save();
+        true
+    }
+
+    /// Id of the newest entry with `text`
+    pub fn find_text(&self, text: &str) -> Option<String> {
+        self.entries.iter().find(|x| x.text == text).map(|x| x.id())
+    }
+
+    /// Id of the newest entry whose image is shown by `path`
+    pub fn find_image(&self, path: &Path) -> Option<String> {
+        self.entries.iter()
+            .find(|x| self.image_file(x).map(|file| file == path).unwrap_or(false))
+            .map(|x| x.id())
+    }
+
+    /// Toggle pinned state, return whether the entry is found
+    pub fn toggle_pin(&mut self, id: &str) -> bool {
+        let found = match self.entries.iter_mut().find(|x| x.id() == id) {
+            Some(entry) => {
+                entry.pinned = !entry.pinned;
+                true
+            },
+            None => false,
+        };
+        if found {
+            self.trim();
+            self.save();
+        }
+        found
+    }
+
+    /// Remove entry, return whether the entry is found
+    pub fn remove(&mut self, id: &str) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|x| x.id() != id);
+        if self.entries.len() == count {
+            return false;
+        }
+        self.trim();
+        self.save();
+        true
+    }
+
+    /// Remove all entries, including pinned ones
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.marked.clear();
+        self.save();
+    }
+
+    /// Toggle marked for joining, return whether the entry is found
+    pub fn toggle_mark(&mut self, id: &str) -> bool {
+        if !self.entries.iter().any(|x| x.id() == id) {
+            return false;
+        }
+        if self.marked.iter().any(|x| x == id) {
+            self.marked.retain(|x| x != id);
+        } else {
+            self.marked.push(id.into());
+        }
+        true
+    }
+
+    pub fn is_marked(&self, entry: &Entry) -> bool {
+        let id = entry.id();
+        self.marked.iter().any(|x| *x == id)
+    }
+
+    /// Text of marked entries, in marking order
+    pub fn marked_texts(&self) -> Vec<String> {
+        self.marked.iter()
+            .filter_map(|id| self.entries.iter().find(|x| x.id() == *id))
+            .map(|x| x.text.clone())
+            .collect()
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.config.max_entries
+    }
+
+    /// Entries, newest first, with expired ones removed
+    pub fn entries(&mut self) -> &VecDeque<Entry> {
+        self.trim();
+        &self.entries
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::collections::{VecDeque, HashSet};
+    use actions::clipboard_store::{encrypt, decrypt, ClipboardStore, Config, Entry, Blob};
+
+    #[test]
+    fn encrypt_test() {
+        let key : Vec<u8> = (0..64).collect();
+        let iv = [7u8; 16];
+        let data = encrypt(&key, &iv, b"hello clipboard");
+        assert!(!data.windows(5).any(|x| x == b"hello"));
+        assert_eq!(decrypt(&key, &data).unwrap(), b"hello clipboard".to_vec());
+
+        let mut tampered = data.clone();
+        let idx = tampered.len() - 40;
+        tampered[idx] ^= 1;
+        assert!(decrypt(&key, &tampered).is_err());
+
+        let other_key : Vec<u8> = (1..65).collect();
+        assert!(decrypt(&other_key, &data).is_err());
+    }
+
+    #[test]
+    fn trim_test() {
+        let entry = |text: &str, pinned: bool, blob_size: usize| Entry {
+            text: text.into(),
+            time: 0,
+            source: None,
+            blobs: if blob_size > 0 {
+                vec![Blob { target: "image/png".into(), hash: text.into(), size: blob_size }]
+            } else {
+                Vec::new()
+            },
+            pinned: pinned,
+        };
+        let mut store = ClipboardStore {
+            config: Config {
+                max_entries: 2,
+                max_age_days: None,
+                max_size: None,
+                max_blob_size: Some(100),
+                exclude: Vec::new(),
+                encrypt: false,
+                key_file: None,
+            },
+            exclude: Vec::new(),
+            key: None,
+            path: PathBuf::from("/nonexistent/clipboard_history"),
+            blob_dir: PathBuf::from("/nonexistent/clipboard_blobs"),
+            entries: VecDeque::new(),
+            marked: Vec::new(),
+            no_thumbnail: HashSet::new(),
+        };
+        store.entries = vec![entry("a", false, 60), entry("b", false, 0), entry("c", true, 80),
+                             entry("d", false, 0), entry("", false, 10)].into_iter().collect();
+        store.trim();
+        let texts : Vec<&str> = store.entries.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b", "c"]);
+        // pinned entry keeps its image, which takes the budget first
+        assert_eq!(store.entries[0].blobs.len(), 0);
+        assert_eq!(store.entries[2].blobs.len(), 1);
+    }
+}
@@ -0,0 +1,343 @@
+//! Search file contents under a directory, like grep
+
+extern crate ignore;
+extern crate shlex;
+
+use toml;
+
+use std::env;
+use std::thread;
+use std::fs::File;
+use std::io::prelude::*;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use mcore::item::{Item, ItemData, Icon};
+use mcore::action::{Action, ActionArg, ActionResult};
+use actions::ActionError;
+use actions::utils::{open, subprocess, terminal};
+
+/// Files containing NUL in this many leading bytes are considered binary
+const BINARY_CHECK_LEN: usize = 8192;
+/// Send partial results at least this often, in milliseconds
+const BATCH_INTERVAL: u64 = 200;
+/// Max length of snippet shown in title
+const MAX_SNIPPET_LEN: usize = 120;
+
+#[derive(Deserialize, Clone)]
+struct Config {
+    /// Command template to open file at line, `%f` is replaced by the file path
+    /// and `%l` by the line number. Empty for $VISUAL or $EDITOR in terminal
+    #[serde(default)]
+    editor: String,
+    /// Run the editor command in terminal
+    #[serde(default)]
+    editor_in_terminal: bool,
+    /// Search hidden files
+    #[serde(default)]
+    hidden: bool,
+    max_results: Option<usize>,
+    /// Skip files larger than this, in bytes
+    max_file_size: Option<u64>,
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config {
+        editor: String::new(),
+        editor_in_terminal: false,
+        hidden: false,
+        max_results: None,
+        max_file_size: None,
+    });
+}
+
+/// Load content search config
+pub fn configure(config: toml::Value) {
+    match config.try_into::<Config>() {
+        Ok(config) => { *CONFIG.write().unwrap() = config; },
+        Err(error) => warn!("Error loading content search config: {}", error),
+    }
+}
+
+/// Search contents under path input
+pub struct ContentSearchAction {}
+
+/// Search contents under `dir` with text input
+pub struct SearchInDirAction {
+    dir: PathBuf,
+}
+
+/// A matching line
+struct MatchAction {
+    dir: PathBuf,
+    path: PathBuf,
+    line: usize,
+    snippet: String,
+}
+
+/// Line numbers (1-based) and content of lines containing `pattern`.
+/// Case insensitive unless `pattern` contains uppercase letters
+fn search_content(content: &str, pattern: &str) -> Vec<(usize, String)> {
+    let case_sensitive = pattern.chars().any(|x| x.is_uppercase());
+    let pattern_lower = pattern.to_lowercase();
+    content.lines().enumerate()
+        .filter(|&(_, line)| if case_sensitive {
+            line.contains(pattern)
+        } else {
+            line.to_lowercase().contains(&pattern_lower)
+        })
+        .map(|(idx, line)| (idx + 1, line.to_string()))
+        .collect()
+}
+
+/// Search in file, skipping binary and large files
+fn search_file(path: &Path, pattern: &str, max_size: u64) -> Vec<(usize, String)> {
+    let mut data = Vec::new();
+    match File::open(path) {
+        Ok(file) => {
+            if let Err(error) = file.take(max_size + 1).read_to_end(&mut data) {
+                debug!("Unable to read {:?}: {}", path, error);
+                return Vec::new();
+            }
+        },
+        Err(error) => {
+            debug!("Unable to open {:?}: {}", path, error);
+            return Vec::new();
+        }
+    }
+    if data.len() as u64 > max_size {
+        trace!("Skipping large file {:?}", path);
+        return Vec::new();
+    }
+    if data.iter().take(BINARY_CHECK_LEN).any(|x| *x == 0) {
+        trace!("Skipping binary file {:?}", path);
+        return Vec::new();
+    }
+    search_content(&String::from_utf8_lossy(&data), pattern)
+}
+
+/// Expand editor template
+fn editor_command(template: &str, path: &Path, line: usize) -> Option<Vec<String>> {
+    let parts = match shlex::split(template) {
+        Some(ref parts) if parts.len() > 0 => parts.clone(),
+        _ => return None,
+    };
+    let path = path.to_string_lossy();
+    let line = line.to_string();
+    let has_placeholder = parts.iter().any(|x| x.contains("%f"));
+    let mut ret : Vec<String> = parts.into_iter()
+        .map(|x| x.replace("%f", &path).replace("%l", &line))
+        .collect();
+    if !has_placeholder {
+        ret.push(path.into_owned());
+    }
+    Some(ret)
+}
+
+/// Open file at line with configured editor, fallback to default application
+fn open_at_line(path: &Path, line: usize) -> Result<(), Box<Error + Send + Sync>> {
+    let config = CONFIG.read().unwrap().clone();
+    let (template, in_terminal) = if config.editor.len() > 0 {
+        (config.editor, config.editor_in_terminal)
+    } else {
+        match env::var("VISUAL").or(env::var("EDITOR")) {
+            Ok(ref editor) if editor.len() > 0 => (format!("{} +%l %f", editor), true),
+            _ => {
+                info!("No editor configured, open: {:?}", path);
+                return open::that(&path.to_string_lossy());
+            },
+        }
+    };
+    let mut cmd = match editor_command(&template, path, line) {
+        Some(cmd) => cmd,
+        None => return Err(Box::new(ActionError::new(&format!("Invalid editor command: {}", template)))),
+    };
+    if in_terminal {
+        cmd = terminal::wrap_command(&cmd)?;
+    }
+    info!("Open {:?} at line {}: {:?}", path, line, cmd);
+    let args : Vec<&str> = cmd[1..].iter().map(|x| x.as_str()).collect();
+    subprocess::spawn(&cmd[0], &args)
+}
+
+impl SearchInDirAction {
+    pub fn new(dir: &Path) -> SearchInDirAction {
+        SearchInDirAction { dir: dir.to_path_buf() }
+    }
+
+    /// Walk files in parallel, send items to `sink` in batches
+    fn search(&self, pattern: &str, sink: &Fn(Vec<Item>), cancel: &AtomicBool) -> ActionResult {
+        if pattern.len() == 0 {
+            return Err(Box::new(ActionError::new("Empty search pattern")));
+        }
+        let config = CONFIG.read().unwrap().clone();
+        let max_results = config.max_results.unwrap_or(500);
+        let max_size = config.max_file_size.unwrap_or(10 * 1024 * 1024);
+
+        let (send_ch, recv_ch) = mpsc::channel::<(PathBuf, usize, String)>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let walker = {
+            let stop = stop.clone();
+            let pattern = pattern.to_string();
+            let mut builder = ignore::WalkBuilder::new(&self.dir);
+            builder.hidden(!config.hidden);
+            let walker = builder.build_parallel();
+            thread::spawn(move || {
+                walker.run(|| {
+                    let stop = stop.clone();
+                    let pattern = pattern.clone();
+                    let send_ch = send_ch.clone();
+                    Box::new(move |entry: Result<ignore::DirEntry, ignore::Error>| {
+                        if stop.load(Ordering::Relaxed) {
+                            return ignore::WalkState::Quit;
+                        }
+                        let entry = match entry {
+                            Ok(entry) => entry,
+                            Err(_) => return ignore::WalkState::Continue,
+                        };
+                        if !entry.file_type().map(|x| x.is_file()).unwrap_or(false) {
+                            return ignore::WalkState::Continue;
+                        }
+                        for (line, text) in search_file(entry.path(), &pattern, max_size) {
+                            if send_ch.send((entry.path().to_path_buf(), line, text)).is_err() {
+                                return ignore::WalkState::Quit;
+                            }
+                        }
+                        ignore::WalkState::Continue
+                    })
+                })
+            })
+        };
+
+        let mut ret = Vec::new();
+        let mut batch = Vec::new();
+        let mut last_batch = Instant::now();
+        loop {
+            if cancel.load(Ordering::SeqCst) {
+                info!("Content search cancelled");
+                break;
+            }
+            match recv_ch.recv_timeout(Duration::from_millis(100)) {
+                Ok((path, line, text)) => {
+                    let mut item = Item::new_action_item(Arc::new(Box::new(MatchAction {
+                        dir: self.dir.clone(),
+                        path: path,
+                        line: line,
+                        snippet: text,
+                    })));
+                    item.priority = ret.len() as i32;
+                    batch.push(item.clone());
+                    ret.push(item);
+                    if ret.len() >= max_results {
+                        break;
+                    }
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if batch.len() > 0 && last_batch.elapsed() >= Duration::from_millis(BATCH_INTERVAL) {
+                sink(::std::mem::replace(&mut batch, Vec::new()));
+                last_batch = Instant::now();
+            }
+        }
+        stop.store(true, Ordering::SeqCst);
+        drop(recv_ch);
+        if let Err(_) = walker.join() {
+            warn!("Content search walker panicked");
+        }
+
+        if ret.len() == 0 {
+            return Err(Box::new(ActionError::new(&format!("No file containing {}", pattern))));
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for ContentSearchAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Search Contents");
+        item.subtitle = Some("Search file contents in directory".into());
+        item.icon = Some(Icon::Character{ch: '\u{f002}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_path(&self) -> bool { true }
+
+    fn run_path(&self, path: &Path) -> ActionResult {
+        let dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new("/")) };
+        Ok(vec![Item::new_action_item(Arc::new(Box::new(SearchInDirAction::new(dir))))])
+    }
+}
+
+impl Action for SearchInDirAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Search Contents…");
+        item.subtitle = Some(format!("Search file contents in {}", self.dir.to_string_lossy()));
+        item.icon = Some(Icon::Character{ch: '\u{f002}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_text(&self) -> bool { true }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        self.search(text, &|_| {}, &AtomicBool::new(false))
+    }
+
+    fn run_arg_stream(&self, arg: &ActionArg, sink: &Fn(Vec<Item>), cancel: &AtomicBool) -> ActionResult {
+        match *arg {
+            ActionArg::Text(ref text) => self.search(text, sink, cancel),
+            _ => self.run_arg(arg),
+        }
+    }
+}
+
+impl Action for MatchAction {
+    fn get_item(&self) -> Item {
+        let rel_path = self.path.strip_prefix(&self.dir).unwrap_or(&self.path);
+        let mut snippet = self.snippet.trim().to_string();
+        if snippet.chars().count() > MAX_SNIPPET_LEN {
+            snippet = snippet.chars().take(MAX_SNIPPET_LEN).collect::<String>() + "…";
+        }
+        let mut item = Item::new(&format!("{}:{}: {}", rel_path.to_string_lossy(), self.line, snippet));
+        item.subtitle = Some(self.path.to_string_lossy().into());
+        item.badge = Some("Match".into());
+        item.icon = Some(Icon::Character{ch: '\u{f0f6}', font: "FontAwesome".into()});
+        item.data = Some(ItemData::Path(self.path.clone()));
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        open_at_line(&self.path, self.line)?;
+        Ok(Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use actions::content_search::{search_content, editor_command};
+
+    #[test]
+    fn search_content_test() {
+        let content = "Hello world\nhello again\nbye\n";
+        assert_eq!(search_content(content, "hello"), vec![(1, "Hello world".into()), (2, "hello again".into())]);
+        assert_eq!(search_content(content, "Hello"), vec![(1, "Hello world".into())]);
+        assert_eq!(search_content(content, "xyz"), vec![]);
+    }
+
+    #[test]
+    fn editor_command_test() {
+        assert_eq!(editor_command("code --goto %f:%l", Path::new("/tmp/a b"), 3),
+                   Some(vec!["code".into(), "--goto".into(), "/tmp/a b:3".into()]));
+        assert_eq!(editor_command("gedit", Path::new("/tmp/a"), 3),
+                   Some(vec!["gedit".into(), "/tmp/a".into()]));
+    }
+}
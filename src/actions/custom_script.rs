@@ -13,6 +13,7 @@ use toml;
 
 use std;
 use std::sync::Arc;
+use std::time::Duration;
 use std::path::{PathBuf, Path};
 use std::error::Error;
 use std::process::{Command, Stdio};
@@ -21,7 +22,8 @@ use std::fs::File;
 use std::io::prelude::*;
 
 use mcore::item::{Item, ItemData, Icon};
-use mcore::action::{Action, ActionArg, ActionResult};
+use mcore::action::{Action, ActionArg, ActionResult, ContentSupport};
+use mcore::content::ContentKind;
 use actions::file_browser::FileBrowserEntry;
 use actions::utils::open;
 use actions::ActionError;
@@ -33,6 +35,8 @@ use actions::ActionError;
 ///     badge (optional)
 /// Each item may have one of following data included:
 ///     data_text, data_path, data_url
+/// Set sensitive to true for secret data (like password), which would be
+/// excluded from clipboard history and cleared from clipboard after a while
 /// And each item may define it's action, that may be one of the followings:
 ///     action_callback: call custom script again, with `action_callback` as command and arguments
 ///                      must also define action_callback_returns: whether this action would return items
@@ -50,6 +54,8 @@ struct ScriptItem {
     data_text: Option<String>,
     data_path: Option<String>,
     data_url: Option<String>,
+    #[serde(default)]
+    sensitive: bool,
 
     action_callback: Option<Vec<String>>,
     action_callback_returns: Option<bool>,
@@ -110,6 +116,11 @@ pub struct ScriptAction {
     accept_nothing_: bool,
     accept_text_: bool,
     accept_path_: bool,
+    /// Kinds of text it suits, if declared
+    content_kinds: Option<Vec<ContentKind>>,
+    search_provider: bool,
+    /// Seconds to cache returned items, 0 to disable
+    cache_ttl: u64,
 
     script: String,
     script_args: Vec<String>,
@@ -166,7 +177,28 @@ impl Action for ScriptAction {
     fn accept_nothing(&self) -> bool { self.accept_nothing_ }
     fn accept_text(&self) -> bool { self.accept_text_ }
     fn accept_path(&self) -> bool { self.accept_path_ }
+    fn content_support(&self, kind: ContentKind) -> ContentSupport {
+        match self.content_kinds {
+            Some(ref kinds) if kinds.contains(&kind) => ContentSupport::Supported,
+            Some(_) => ContentSupport::Unsupported,
+            None => ContentSupport::Unknown,
+        }
+    }
     fn should_return_items(&self) -> bool { self.script_returns }
+    fn is_search_provider(&self) -> bool {
+        self.search_provider && self.accept_nothing_ && self.script_returns
+    }
+    fn uid(&self) -> String {
+        let mut ret = self.script_dir.join(&self.script).to_string_lossy().into_owned();
+        for arg in self.script_args.iter() {
+            ret.push(' ');
+            ret.push_str(arg);
+        }
+        ret
+    }
+    fn cache_ttl(&self) -> Option<Duration> {
+        if self.cache_ttl > 0 { Some(Duration::from_secs(self.cache_ttl)) } else { None }
+    }
     fn run(&self) -> ActionResult {
         let mut cmd = Command::new(&self.script_dir.join(&self.script));
         cmd.args(&self.script_args);
@@ -210,6 +242,9 @@ impl ScriptItem {
                     accept_nothing_: true,
                     accept_text_: false,
                     accept_path_: false,
+                    content_kinds: None,
+                    search_provider: false,
+                    cache_ttl: 0,
                     script: action_callback[0].clone(),
                     script_args: action_callback.into_iter().skip(1).collect(),
                     script_returns: self.action_callback_returns.unwrap_or(false),
@@ -246,6 +281,7 @@ impl ScriptItem {
             priority: 0,
             data: itemdata,
             search_str: None,
+            sensitive: self.sensitive,
             action: match action {
                 Some(x) => Some(Arc::new(x)),
                 None => None,
@@ -271,6 +307,12 @@ struct ScriptMetadata {
     accept_nothing: Option<bool>,
     accept_text: Option<bool>,
     accept_path: Option<bool>,
+    /// Kinds of text this script suits, e.g. ["url", "email"], see ContentKind
+    content_kinds: Option<Vec<ContentKind>>,
+    /// Whether items returned without input are searched from the root query
+    search_provider: Option<bool>,
+    /// Seconds to cache returned items for the same input
+    cache_ttl: Option<u64>,
 
     requirements: Option<Vec<String>>,
 }
@@ -331,6 +373,9 @@ impl ScriptAction {
             accept_nothing_: metadata.accept_nothing.unwrap_or(false),
             accept_text_: metadata.accept_text.unwrap_or(false),
             accept_path_: metadata.accept_path.unwrap_or(false),
+            content_kinds: metadata.content_kinds,
+            search_provider: metadata.search_provider.unwrap_or(false),
+            cache_ttl: metadata.cache_ttl.unwrap_or(0),
             script: metadata.script,
             script_args: Vec::new(),
             script_returns: metadata.script_returns,
@@ -0,0 +1,206 @@
+//! Mounted filesystems and removable devices
+
+extern crate libc;
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::mem;
+use std::ffi::CString;
+use std::sync::Arc;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::os::unix::ffi::OsStrExt;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::{FileBrowserEntry, format_size};
+use actions::utils::mountinfo::{self, MountInfo};
+
+/// Filesystem types not backed by a device, besides those with non-path sources
+const IGNORED_FS_TYPES: &[&str] = &["squashfs", "overlay", "autofs", "fuse.gvfsd-fuse", "fuse.portal"];
+/// Network filesystems, whose sources are not paths
+const NETWORK_FS_TYPES: &[&str] = &["nfs", "nfs4", "cifs", "smb3", "9p"];
+
+pub struct DevicesAction {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UdisksOperation {
+    Unmount,
+    /// Unmount, then power off the drive
+    Eject,
+}
+
+struct UdisksAction {
+    op: UdisksOperation,
+    mount_point: PathBuf,
+    /// Block device of the filesystem, like /dev/sdb1
+    device: PathBuf,
+    /// Block device of the whole drive, like /dev/sdb
+    drive: PathBuf,
+}
+
+struct SpaceInfo {
+    total: u64,
+    free: u64,
+    used: u64,
+}
+
+fn statvfs(path: &Path) -> Option<SpaceInfo> {
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return None,
+    };
+    unsafe {
+        let mut stat : libc::statvfs = mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        let frsize = stat.f_frsize as u64;
+        Some(SpaceInfo {
+            total: stat.f_blocks as u64 * frsize,
+            free: stat.f_bavail as u64 * frsize,
+            used: (stat.f_blocks as u64 - stat.f_bfree as u64) * frsize,
+        })
+    }
+}
+
+fn is_device_mount(mount: &MountInfo) -> bool {
+    if IGNORED_FS_TYPES.contains(&mount.fs_type.as_str()) {
+        return false;
+    }
+    mount.source.starts_with('/') || mount.fs_type.starts_with("fuse.") ||
+        NETWORK_FS_TYPES.contains(&mount.fs_type.as_str())
+}
+
+/// Block device of the drive containing `device`, if it's removable
+fn removable_drive(device: &str) -> Option<PathBuf> {
+    let device = match fs::canonicalize(device) {
+        Ok(device) => device,
+        Err(_) => return None,
+    };
+    let name = match device.file_name() {
+        Some(name) => name.to_os_string(),
+        None => return None,
+    };
+    let sys_path = match fs::canonicalize(Path::new("/sys/class/block").join(&name)) {
+        Ok(path) => path,
+        Err(_) => return None,
+    };
+    let drive_sys_path = if sys_path.join("partition").exists() {
+        sys_path.parent().unwrap_or(&sys_path).to_path_buf()
+    } else {
+        sys_path
+    };
+    let mut removable = String::new();
+    if let Ok(mut file) = File::open(drive_sys_path.join("removable")) {
+        let _ = file.read_to_string(&mut removable);
+    }
+    // USB hard drives are not marked as removable
+    if removable.trim() == "1" || drive_sys_path.to_string_lossy().contains("/usb") {
+        drive_sys_path.file_name().map(|x| Path::new("/dev").join(x))
+    } else {
+        None
+    }
+}
+
+fn run_udisksctl(args: &[&str]) -> Result<(), Box<Error + Send + Sync>> {
+    info!("Running udisksctl {:?}", args);
+    let output = Command::new("udisksctl").args(args).output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(Box::new(ActionError::new(&format!("udisksctl {} failed: {}", args[0], reason))))
+    }
+}
+
+impl Action for DevicesAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Devices");
+        item.subtitle = Some("Mounted filesystems and removable devices".into());
+        item.icon = Some(Icon::Character{ch: '\u{f0a0}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let mut mounts : Vec<MountInfo> = mountinfo::get_mounts().into_iter()
+            .filter(is_device_mount)
+            .collect();
+        mounts.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+        mounts.dedup_by(|a, b| a.mount_point == b.mount_point);
+
+        let mut ret = Vec::new();
+        for mount in mounts {
+            let name = match mount.mount_point.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => mount.mount_point.to_string_lossy().into_owned(),
+            };
+            let entry = match FileBrowserEntry::new(name, mount.mount_point.clone()) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut item = Item::new_action_item(Arc::new(Box::new(entry)));
+            item.badge = Some(mount.fs_type.clone());
+            item.subtitle = Some(match statvfs(&mount.mount_point) {
+                Some(space) => format!("{} free of {}, {} used  {}  {}",
+                                       format_size(space.free), format_size(space.total),
+                                       format_size(space.used),
+                                       mount.source, mount.mount_point.to_string_lossy()),
+                None => format!("{}  {}", mount.source, mount.mount_point.to_string_lossy()),
+            });
+            item.priority = ret.len() as i32;
+            ret.push(item);
+
+            if let Some(drive) = removable_drive(&mount.source) {
+                for op in vec![UdisksOperation::Unmount, UdisksOperation::Eject] {
+                    let mut item = Item::new_action_item(Arc::new(Box::new(UdisksAction {
+                        op: op,
+                        mount_point: mount.mount_point.clone(),
+                        device: PathBuf::from(&mount.source),
+                        drive: drive.clone(),
+                    })));
+                    item.priority = ret.len() as i32;
+                    ret.push(item);
+                }
+            }
+        }
+        if ret.len() == 0 {
+            return Err(Box::new(ActionError::new("No mounted filesystem")));
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for UdisksAction {
+    fn get_item(&self) -> Item {
+        let (title, ch) = match self.op {
+            UdisksOperation::Unmount => ("Unmount", '\u{f056}'),
+            UdisksOperation::Eject => ("Eject", '\u{f052}'),
+        };
+        let mut item = Item::new(&format!("{} {}", title, self.mount_point.to_string_lossy()));
+        item.subtitle = Some(match self.op {
+            UdisksOperation::Unmount => self.device.to_string_lossy().into_owned(),
+            UdisksOperation::Eject => format!("Unmount and power off {}", self.drive.to_string_lossy()),
+        });
+        item.badge = Some("Removable".into());
+        item.icon = Some(Icon::Character{ch: ch, font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        let device = self.device.to_string_lossy();
+        run_udisksctl(&["unmount", "-b", &device])?;
+        if self.op == UdisksOperation::Eject {
+            run_udisksctl(&["power-off", "-b", &self.drive.to_string_lossy()])?;
+        }
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,389 @@
+//! Jump to frequently and recently visited directories, like zoxide or autojump
+
+extern crate chrono;
+extern crate serde_json;
+
+use self::chrono::Local;
+
+use toml;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::thread;
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::process::Command;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::FileBrowserEntry;
+use mcore::xdg;
+use actions::utils::{self, terminal, subprocess};
+
+/// Age all entries when total rank exceeds this, same as zoxide
+const MAX_TOTAL_RANK: f64 = 10000.0;
+
+/// Visits are saved to disk in background at most this often, in seconds
+const SAVE_INTERVAL: u64 = 10;
+
+#[derive(Deserialize)]
+struct Config {
+    /// Databases to import on startup: zoxide, autojump or fasd
+    #[serde(default)]
+    import: Vec<String>,
+    max_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DirRecord {
+    rank: f64,
+    /// Unix timestamp
+    last_access: i64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Database {
+    dirs: HashMap<PathBuf, DirRecord>,
+}
+
+lazy_static! {
+    static ref DATABASE: Mutex<Database> = Mutex::new(Database::load());
+    /// Visits are only recorded if dir_jump is configured
+    static ref ENABLED: AtomicBool = AtomicBool::new(false);
+    /// Visits not saved yet
+    static ref DIRTY: AtomicBool = AtomicBool::new(false);
+}
+
+impl Database {
+    fn db_file() -> PathBuf {
+        utils::minions_dir().join("dirjump.json")
+    }
+
+    fn load() -> Database {
+        match File::open(Database::db_file()) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|error| {
+                warn!("Invalid directory jump database: {}", error);
+                Database::default()
+            }),
+            Err(_) => Database::default(),
+        }
+    }
+
+    fn save(&self) {
+        let tmp_file = Database::db_file().with_extension("tmp");
+        let result = File::create(&tmp_file)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::to_writer(file, self).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_file, Database::db_file()).map_err(|e| e.to_string()));
+        if let Err(error) = result {
+            warn!("Unable to save directory jump database: {}", error);
+        }
+    }
+
+    fn visit(&mut self, dir: &Path, now: i64) {
+        {
+            let entry = self.dirs.entry(dir.to_path_buf())
+                .or_insert(DirRecord { rank: 0.0, last_access: now });
+            entry.rank += 1.0;
+            entry.last_access = now;
+        }
+        let total : f64 = self.dirs.values().map(|x| x.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            for entry in self.dirs.values_mut() {
+                entry.rank *= 0.9;
+            }
+            self.dirs.retain(|_, x| x.rank >= 1.0);
+        }
+    }
+
+    /// Merge imported entries, keeping the higher rank and the later access time
+    fn merge(&mut self, entries: Vec<(PathBuf, DirRecord)>) {
+        for (path, imported) in entries {
+            let entry = self.dirs.entry(path).or_insert(imported.clone());
+            if imported.rank > entry.rank {
+                entry.rank = imported.rank;
+            }
+            if imported.last_access > entry.last_access {
+                entry.last_access = imported.last_access;
+            }
+        }
+    }
+}
+
+/// Rank weighted by how recently the directory was accessed
+fn frecency(entry: &DirRecord, now: i64) -> f64 {
+    let age = now - entry.last_access;
+    entry.rank * if age < 3600 {
+        4.0
+    } else if age < 86400 {
+        2.0
+    } else if age < 604800 {
+        0.5
+    } else {
+        0.25
+    }
+}
+
+/// Whether `path` matches all `keywords` in order, and the last keyword
+/// matches the last component
+fn matches(path: &str, keywords: &[String]) -> bool {
+    let path = path.to_lowercase();
+    let mut rest = path.as_str();
+    for keyword in keywords.iter() {
+        match rest.find(keyword.as_str()) {
+            Some(pos) => rest = &rest[pos + keyword.len()..],
+            None => return false,
+        }
+    }
+    match keywords.last() {
+        Some(last) => {
+            let name = path.rsplit('/').next().unwrap_or("");
+            name.contains(last.as_str())
+        },
+        None => true,
+    }
+}
+
+/// Record that `dir` is visited, saved later by the background thread
+pub fn record_visit(dir: &Path) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    DATABASE.lock().unwrap().visit(dir, Local::now().timestamp());
+    DIRTY.store(true, Ordering::SeqCst);
+}
+
+/// Save the database periodically if there are new visits
+fn start_saving() {
+    if ENABLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_secs(SAVE_INTERVAL));
+        if DIRTY.swap(false, Ordering::SeqCst) {
+            DATABASE.lock().unwrap().save();
+        }
+    });
+}
+
+fn import_zoxide() -> Vec<(PathBuf, DirRecord)> {
+    let output = match Command::new("zoxide").args(&["query", "--list", "--score"]).output() {
+        Ok(output) => output,
+        Err(error) => {
+            warn!("Unable to run zoxide: {}", error);
+            return Vec::new();
+        }
+    };
+    // zoxide does not report access time
+    let now = Local::now().timestamp();
+    String::from_utf8_lossy(&output.stdout).lines().filter_map(|line| {
+        let line = line.trim();
+        let pos = match line.find(' ') {
+            Some(pos) => pos,
+            None => return None,
+        };
+        line[..pos].parse::<f64>().ok().map(|rank| {
+            (PathBuf::from(line[pos..].trim()), DirRecord { rank: rank, last_access: now - 604800 })
+        })
+    }).collect()
+}
+
+/// Lines of `weight\tpath`
+fn parse_autojump(content: &str, now: i64) -> Vec<(PathBuf, DirRecord)> {
+    content.lines().filter_map(|line| {
+        let mut parts = line.splitn(2, '\t');
+        match (parts.next().and_then(|x| x.parse::<f64>().ok()), parts.next()) {
+            (Some(rank), Some(path)) => Some((PathBuf::from(path),
+                                              DirRecord { rank: rank, last_access: now - 604800 })),
+            _ => None,
+        }
+    }).collect()
+}
+
+/// Lines of `path|rank|time`
+fn parse_fasd(content: &str) -> Vec<(PathBuf, DirRecord)> {
+    content.lines().filter_map(|line| {
+        let parts : Vec<&str> = line.rsplitn(3, '|').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        match (parts[1].parse::<f64>(), parts[0].parse::<i64>()) {
+            (Ok(rank), Ok(time)) => Some((PathBuf::from(parts[2]),
+                                          DirRecord { rank: rank, last_access: time })),
+            _ => None,
+        }
+    }).collect()
+}
+
+fn read_file(path: &Path) -> String {
+    let mut content = String::new();
+    if let Ok(mut file) = File::open(path) {
+        if let Err(error) = file.read_to_string(&mut content) {
+            warn!("Unable to read {:?}: {}", path, error);
+        }
+    }
+    content
+}
+
+fn import(source: &str) -> Vec<(PathBuf, DirRecord)> {
+    let home = env::home_dir().unwrap_or(PathBuf::from("/"));
+    let entries = match source {
+        "zoxide" => import_zoxide(),
+        "autojump" => parse_autojump(&read_file(&xdg::data_home().join("autojump/autojump.txt")),
+                                     Local::now().timestamp()),
+        "fasd" => {
+            let path = env::var("_FASD_DATA").map(PathBuf::from)
+                .unwrap_or(home.join(".fasd"));
+            parse_fasd(&read_file(&path))
+        },
+        _ => {
+            warn!("Unknown directory jump database: {}", source);
+            Vec::new()
+        },
+    };
+    info!("Imported {} directories from {}", entries.len(), source);
+    // fasd also records files
+    entries.into_iter().filter(|x| x.0.is_dir()).collect()
+}
+
+pub struct DirJumpAction {
+    max_results: usize,
+}
+
+/// Open terminal in directory
+pub struct TerminalHereAction {}
+
+impl DirJumpAction {
+    pub fn new(config: toml::Value) -> DirJumpAction {
+        let config = match config.try_into::<Config>() {
+            Ok(config) => config,
+            Err(error) => {
+                warn!("Error loading directory jump config: {}", error);
+                Config { import: Vec::new(), max_results: None }
+            }
+        };
+        if config.import.len() > 0 {
+            let mut db = DATABASE.lock().unwrap();
+            for source in config.import.iter() {
+                db.merge(import(source));
+            }
+            db.save();
+        }
+        start_saving();
+        DirJumpAction {
+            max_results: config.max_results.unwrap_or(20),
+        }
+    }
+}
+
+impl Action for DirJumpAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Jump to Directory");
+        item.subtitle = Some("Frequently and recently visited directories".into());
+        item.priority = -10;
+        item.icon = Some(Icon::Character{ch: '\u{f07c}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+    fn accept_text(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        self.run_text("")
+    }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        let keywords : Vec<String> = text.split_whitespace().map(|x| x.to_lowercase()).collect();
+        let now = Local::now().timestamp();
+        let mut dirs : Vec<(f64, PathBuf)> = {
+            let db = DATABASE.lock().unwrap();
+            db.dirs.iter()
+                .filter(|&(path, _)| matches(&path.to_string_lossy(), &keywords))
+                .map(|(path, entry)| (frecency(entry, now), path.clone()))
+                .collect()
+        };
+        dirs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let ret : Vec<Item> = dirs.into_iter()
+            .filter(|x| x.1.is_dir())
+            .filter_map(|(_, path)| {
+                let name = match path.file_name() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => path.to_string_lossy().into_owned(),
+                };
+                FileBrowserEntry::new(name, path)
+            })
+            .take(self.max_results)
+            .enumerate()
+            .map(|(idx, entry)| {
+                let mut item = Item::new_action_item(Arc::new(Box::new(entry)));
+                item.priority = idx as i32;
+                item
+            })
+            .collect();
+        if ret.len() == 0 {
+            return Err(Box::new(ActionError::new(&format!("No directory matching {}", text))));
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for TerminalHereAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open Terminal Here");
+        item.icon = Some(Icon::Character{ch: '\u{f120}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_path(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run_path(&self, path: &Path) -> ActionResult {
+        let dir = if path.is_dir() { path } else { path.parent().unwrap_or(Path::new("/")) };
+        let shell = env::var("SHELL").unwrap_or("sh".into());
+        let cmd = terminal::wrap_command(&[
+            "sh".into(), "-c".into(), "cd \"$1\" && exec \"$2\"".into(), "sh".into(),
+            dir.to_string_lossy().into_owned(), shell,
+        ])?;
+        info!("Open terminal in {:?}", dir);
+        let args : Vec<&str> = cmd[1..].iter().map(|x| x.as_str()).collect();
+        subprocess::spawn(&cmd[0], &args)?;
+        Ok(Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use actions::dir_jump::{matches, parse_autojump, parse_fasd};
+
+    #[test]
+    fn matches_test() {
+        let kw = |s: &str| s.split_whitespace().map(|x| x.to_string()).collect::<Vec<String>>();
+        assert!(matches("/home/user/projects/minions", &kw("min")));
+        assert!(matches("/home/user/projects/minions", &kw("proj min")));
+        assert!(!matches("/home/user/projects/minions", &kw("min proj")));
+        assert!(!matches("/home/user/projects/minions", &kw("user")));
+        assert!(matches("/home/user", &kw("")));
+    }
+
+    #[test]
+    fn parse_databases_test() {
+        let entries = parse_autojump("12.5\t/home/user/a b\ninvalid\n", 1000);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, PathBuf::from("/home/user/a b"));
+        assert_eq!(entries[0].1.rank, 12.5);
+
+        let entries = parse_fasd("/home/user/a|b|3.5|1500000000\n");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, PathBuf::from("/home/user/a|b"));
+        assert_eq!(entries[0].1.last_access, 1500000000);
+    }
+}
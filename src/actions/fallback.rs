@@ -0,0 +1,169 @@
+//! Fallback actions taking the filter query as text, when nothing else matches
+
+extern crate chrono;
+
+use self::chrono::Local;
+
+use toml;
+
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionArg, ActionResult};
+use mcore::content::{self, ContentKind};
+use actions::search_engine::SearchEngine;
+use actions::open_url::OpenUrlAction;
+use actions::utils::{self, open, subprocess, terminal};
+
+/// Run query as shell command
+struct ShellCommandAction {
+    in_terminal: bool,
+}
+
+/// Open query as URL, email address or existing path
+struct OpenTargetAction {}
+
+/// Append query to note file
+struct NoteAction {
+    file: PathBuf,
+}
+
+impl Action for ShellCommandAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Run Command");
+        item.badge = Some("Shell".into());
+        item.icon = Some(Icon::Character{ch: '\u{f120}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_text(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        info!("Run command: {}", text);
+        if self.in_terminal {
+            let cmd = terminal::wrap_command(&[
+                "sh".into(), "-c".into(),
+                "sh -c \"$1\"; printf '\\nPress Enter to close'; read _".into(),
+                "sh".into(), text.into(),
+            ])?;
+            let args : Vec<&str> = cmd[1..].iter().map(|x| x.as_str()).collect();
+            subprocess::spawn(&cmd[0], &args)?;
+        } else {
+            subprocess::spawn("sh", &["-c", text])?;
+        }
+        Ok(Vec::new())
+    }
+}
+
+impl Action for OpenTargetAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open");
+        item.badge = Some("Open".into());
+        item.icon = Some(Icon::Character{ch: '\u{f08e}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_arg(&self, arg: &ActionArg) -> bool {
+        match arg {
+            &ActionArg::Text(ref text) => match content::classify(text) {
+                ContentKind::Url | ContentKind::Email => true,
+                ContentKind::Path => utils::expand_path(text.trim()).exists(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        if content::classify(text) == ContentKind::Path {
+            let path = utils::expand_path(text.trim());
+            info!("open: {:?}", path);
+            open::that(&path.to_string_lossy())?;
+            Ok(Vec::new())
+        } else {
+            OpenUrlAction{}.run_text(text)
+        }
+    }
+}
+
+impl Action for NoteAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Create Note");
+        item.subtitle = Some(format!("Append to {}", self.file.to_string_lossy()));
+        item.badge = Some("Note".into());
+        item.icon = Some(Icon::Character{ch: '\u{f249}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_text(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.file)?;
+        writeln!(file, "- {} {}", Local::now().format("%Y-%m-%d %H:%M"), text.trim())?;
+        info!("Note appended to {:?}", self.file);
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Deserialize)]
+struct Config {
+    /// "search", "open", "shell" or "note", in this order
+    items: Vec<String>,
+    /// Name of the search engine, default to the first one
+    search_engine: Option<String>,
+    /// Default to true
+    shell_in_terminal: Option<bool>,
+    /// Default to ~/.minions/notes.md
+    note_file: Option<String>,
+}
+
+/// Fallback actions, `search_config` is the search_engine section
+pub fn get_all(config: toml::Value, search_config: Option<toml::Value>) -> Vec<Arc<Box<Action + Sync + Send>>> {
+    let config = match config.try_into::<Config>() {
+        Ok(config) => config,
+        Err(error) => {
+            warn!("Error loading fallback config: {}", error);
+            return Vec::new();
+        }
+    };
+    let mut ret : Vec<Arc<Box<Action + Sync + Send>>> = Vec::new();
+    for name in config.items.iter() {
+        match name.as_str() {
+            "search" => {
+                let mut engines = search_config.clone().map(SearchEngine::get_all).unwrap_or(Vec::new());
+                let idx = match config.search_engine {
+                    Some(ref name) => engines.iter().position(|x| x.get_item().title == *name),
+                    None => if engines.len() > 0 { Some(0) } else { None },
+                };
+                match idx {
+                    Some(idx) => ret.push(Arc::new(Box::new(engines.swap_remove(idx)))),
+                    None => warn!("Search engine for fallback not found"),
+                }
+            },
+            "open" => ret.push(Arc::new(Box::new(OpenTargetAction{}))),
+            "shell" => ret.push(Arc::new(Box::new(ShellCommandAction {
+                in_terminal: config.shell_in_terminal.unwrap_or(true),
+            }))),
+            "note" => ret.push(Arc::new(Box::new(NoteAction {
+                file: match config.note_file {
+                    Some(ref path) => utils::expand_path(path),
+                    None => utils::minions_dir().join("notes.md"),
+                },
+            }))),
+            _ => warn!("Unknown fallback item: {}", name),
+        }
+    }
+    ret
+}
@@ -5,19 +5,128 @@
 * @Last Modified time: 2017-07-09
 */
 
+extern crate chrono;
+extern crate serde_json;
+
+use self::chrono::{Local, TimeZone};
+
 use toml;
 
-use std::sync::Arc;
+use std::fs::{self, File};
+use std::sync::{Arc, Mutex};
 use std::path::{PathBuf, Path};
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+use std::os::unix::fs::PermissionsExt;
 
 use mcore::item::{Item, ItemData, Icon};
 use mcore::action::{Action, ActionResult};
-use actions::utils::open;
+use actions::ActionError;
+use actions::utils::{self, open};
+use actions::archive;
+use actions::open_with;
+use actions::dir_jump;
+use actions::content_search::SearchInDirAction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum SortMode {
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "mtime")]
+    Modified,
+    #[serde(rename = "size")]
+    Size,
+}
+
+/// Sorting of one directory
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct SortOrder {
+    mode: SortMode,
+    dirs_first: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> SortOrder {
+        SortOrder { mode: SortMode::Name, dirs_first: true }
+    }
+}
+
+/// Browser state persisted across runs
+#[derive(Serialize, Deserialize, Default)]
+struct BrowserState {
+    show_hidden: bool,
+    sort_orders: HashMap<PathBuf, SortOrder>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<BrowserState> = Mutex::new(BrowserState::load());
+}
+
+impl BrowserState {
+    fn state_file() -> PathBuf {
+        utils::minions_dir().join("file_browser.json")
+    }
+
+    fn load() -> BrowserState {
+        match File::open(BrowserState::state_file()) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_else(|error| {
+                warn!("Invalid file browser state: {}", error);
+                BrowserState::default()
+            }),
+            Err(_) => BrowserState::default(),
+        }
+    }
+
+    fn save(&self) {
+        let tmp_file = BrowserState::state_file().with_extension("tmp");
+        let result = File::create(&tmp_file)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::to_writer(file, self).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_file, BrowserState::state_file()).map_err(|e| e.to_string()));
+        if let Err(error) = result {
+            warn!("Unable to save file browser state: {}", error);
+        }
+    }
+
+    fn sort_order(&self, dir: &Path) -> SortOrder {
+        self.sort_orders.get(dir).cloned().unwrap_or_default()
+    }
+}
 
 pub struct FileBrowserEntry {
     name: String,
     path: PathBuf,
     is_file: bool,
+    /// Target, if it's a symbolic link
+    link_target: Option<PathBuf>,
+    /// It's a symbolic link pointing to nothing
+    is_broken: bool,
+    size: u64,
+    mtime: i64,
+    mode: u32,
+}
+
+/// Open file with the default opener, first child of a file
+struct OpenFileAction {
+    path: PathBuf,
+}
+
+/// View option change for a directory
+enum BrowserSetting {
+    Sort(SortMode),
+    DirsFirst(bool),
+    ShowHidden(bool),
+}
+
+/// Change view option and list the directory again
+struct BrowserSettingAction {
+    dir: PathBuf,
+    setting: BrowserSetting,
+}
+
+/// List view options for a directory
+struct BrowserSettingsAction {
+    dir: PathBuf,
 }
 
 
@@ -32,19 +141,67 @@ struct Config {
     entries: Vec<EntryConfig>,
 }
 
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+fn format_mode(mode: u32, is_dir: bool, is_link: bool) -> String {
+    let mut ret = String::new();
+    ret.push(if is_link { 'l' } else if is_dir { 'd' } else { '-' });
+    for shift in [6, 3, 0].iter() {
+        let bits = (mode >> shift) & 0o7;
+        ret.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        ret.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        ret.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    ret
+}
+
 impl FileBrowserEntry {
     pub fn new(name: String, path: PathBuf) -> Option<FileBrowserEntry> {
-        if ! (path.is_dir() || path.is_file()) {
-            warn!("Invalid path: {:?}", path);
-            None
-        } else {
-            let is_file = path.is_file();
-            Some(FileBrowserEntry {
-                name: name,
-                path: path,
-                is_file: is_file,
-            })
-        }
+        let link_meta = match fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(_) => {
+                warn!("Invalid path: {:?}", path);
+                return None;
+            }
+        };
+        let link_target = if link_meta.file_type().is_symlink() {
+            fs::read_link(&path).ok()
+        } else { None };
+        // for symbolic links, describe the target if possible
+        let (meta, is_broken) = match fs::metadata(&path) {
+            Ok(meta) => (meta, false),
+            Err(_) => (link_meta, true),
+        };
+        let mtime = match meta.modified() {
+            Ok(t) => match t.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_secs() as i64,
+                Err(_) => 0,
+            },
+            Err(_) => 0,
+        };
+        Some(FileBrowserEntry {
+            name: name,
+            path: path,
+            is_file: !meta.is_dir(),
+            link_target: link_target,
+            is_broken: is_broken,
+            size: meta.len(),
+            mtime: mtime,
+            mode: meta.permissions().mode(),
+        })
     }
 
     pub fn get_all(config: toml::Value) -> Vec<FileBrowserEntry> {
@@ -57,24 +214,131 @@ impl FileBrowserEntry {
 
         config.entries.into_iter()
         .map(|c| {
-            FileBrowserEntry::new(c.name, Path::new(&c.path).to_path_buf())
+            FileBrowserEntry::new(c.name, utils::expand_path(&c.path))
         })
         .filter(|x| x.is_some())
         .map(|x| x.unwrap())
         .collect()
     }
+
+    fn describe(&self) -> String {
+        let mut ret = format_mode(self.mode, !self.is_file, self.link_target.is_some());
+        if self.is_file && !self.is_broken {
+            ret += &format!("  {}", format_size(self.size));
+        }
+        ret += &format!("  {}", Local.timestamp(self.mtime, 0).format("%Y-%m-%d %H:%M"));
+        if let Some(ref target) = self.link_target {
+            ret += &format!("  → {}", target.to_string_lossy());
+        }
+        ret
+    }
+
+    /// Archives are browsed like directories
+    fn is_archive(&self) -> bool {
+        self.is_file && !self.is_broken && archive::is_archive(&self.path)
+    }
+
+    /// Children of a file: open it, or with one of the applications
+    fn list_file(&self) -> ActionResult {
+        let mut open_item = Item::new_action_item(Arc::new(Box::new(OpenFileAction {
+            path: self.path.clone(),
+        })));
+        open_item.priority = -100;
+        let mut ret = vec![open_item];
+        match open_with::list(&self.path) {
+            Ok(items) => ret.extend(items),
+            Err(error) => debug!("No application to open with: {}", error),
+        }
+        Ok(ret)
+    }
+
+    fn into_item(self) -> Item {
+        let mut item = self.get_item();
+        item.action = Some(Arc::new(Box::new(self)));
+        item
+    }
+
+    /// List directory `dir`, according to its view options
+    fn list_dir(dir: &Path) -> ActionResult {
+        let (show_hidden, order) = {
+            let state = STATE.lock().unwrap();
+            (state.show_hidden, state.sort_order(dir))
+        };
+
+        debug!("Reading dir: {:?}", dir);
+        let mut entries = Vec::new();
+        for entry in dir.read_dir()? {
+            match entry {
+                Ok(entry) => {
+                    let name : String = entry.file_name().to_string_lossy().into();
+                    if !show_hidden && name.starts_with('.') {
+                        continue;
+                    }
+                    if let Some(act) = FileBrowserEntry::new(name, entry.path()) {
+                        entries.push(act);
+                    }
+                },
+                Err(error) => {
+                    warn!("Read dir error: {}", error);
+                }
+            }
+        }
+
+        entries.sort_by(|a, b| {
+            let dirs_first = if order.dirs_first { a.is_file.cmp(&b.is_file) }
+                             else { ::std::cmp::Ordering::Equal };
+            let by_mode = match order.mode {
+                SortMode::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                SortMode::Modified => b.mtime.cmp(&a.mtime),
+                SortMode::Size => b.size.cmp(&a.size),
+            };
+            dirs_first.then(by_mode)
+        });
+
+        let mut ret : Vec<Item> = entries.into_iter().enumerate().map(|(idx, act)| {
+            let mut item = act.into_item();
+            item.priority = idx as i32;
+            item
+        }).collect();
+
+        let mut settings_item = Item::new_action_item(Arc::new(Box::new(BrowserSettingsAction {
+            dir: dir.to_path_buf(),
+        })));
+        settings_item.priority = -90;
+        ret.push(settings_item);
+
+        let mut search_item = Item::new_action_item(Arc::new(Box::new(SearchInDirAction::new(dir))));
+        search_item.priority = -85;
+        ret.push(search_item);
+
+        if let Some(parent) = dir.parent() {
+            if let Some(act) = FileBrowserEntry::new("..".into(), parent.into()) {
+                let mut item = act.into_item();
+                item.priority = -100;
+                ret.push(item);
+            }
+        }
+        Ok(ret)
+    }
 }
 
 
 impl Action for FileBrowserEntry {
     fn get_item (&self) -> Item {
         let mut ret = Item::new(&self.name);
-        ret.subtitle = Some(self.path.to_string_lossy().into());
-        ret.badge = if self.is_file {
-            Some("File".into())
+        // path first, as file search, recent files and dir jump list entries from anywhere
+        ret.subtitle = Some(format!("{}  {}", self.path.to_string_lossy(), self.describe()));
+        ret.badge = Some(if self.is_broken {
+            "Broken Link"
+        } else if self.link_target.is_some() {
+            "Link"
+        } else if self.is_archive() {
+            "Archive"
+        } else if self.is_file {
+            "File"
         } else {
-            Some("Directory".into())
-        };
+            "Directory"
+        }.into());
         ret.icon = Some(if self.is_file {
             Icon::Character{ch: '', font: "FontAwesome".into()}
         } else {
@@ -87,41 +351,109 @@ impl Action for FileBrowserEntry {
 
     fn accept_nothing(&self) -> bool { true }
 
-    fn should_return_items(&self) -> bool { !self.is_file }
-
     fn run(&self) -> ActionResult {
-        if self.is_file {
-            info!("open: {:?}", self.path);
-            open::that(&self.path.to_string_lossy())?;
-            Ok(Vec::new())
+        if self.is_broken {
+            Err(Box::new(ActionError::new(&format!("Broken symbolic link: {:?}", self.path))))
+        } else if self.is_archive() {
+            archive::list(&self.path)
+        } else if self.is_file {
+            self.list_file()
         } else {
-            let mut ret = Vec::new();
-
-            debug!("Reading dir: {:?}", self.path);
-            let entries = self.path.read_dir()?;
-            for entry in entries.into_iter() {
-                match entry {
-                    Ok(entry) => {
-                        if let Some(act) = FileBrowserEntry::new(entry.file_name().to_string_lossy().into(), entry.path()) {
-                            let mut item = act.get_item();
-                            item.action = Some(Arc::new(Box::new(act)));
-                            ret.push(item);
-                        }
-                    },
-                    Err(error) => {
-                        warn!("Read dir error: {}", error);
-                    }
-                }
-            }
-            if let Some(parent) = self.path.parent() {
-                if let Some(act) = FileBrowserEntry::new("..".into(), parent.into()) {
-                    let mut item = act.get_item();
-                    item.action = Some(Arc::new(Box::new(act)));
-                    item.priority = -100;
-                    ret.push(item);
-                }
+            dir_jump::record_visit(&self.path);
+            FileBrowserEntry::list_dir(&self.path)
+        }
+    }
+}
+
+
+impl Action for OpenFileAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open");
+        item.subtitle = Some("With default application".into());
+        item.icon = Some(Icon::Character{ch: '\u{f08e}', font: "FontAwesome".into()});
+        item.data = Some(ItemData::Path(self.path.clone()));
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        info!("open: {:?}", self.path);
+        open::that(&self.path.to_string_lossy())?;
+        Ok(Vec::new())
+    }
+}
+
+impl Action for BrowserSettingsAction {
+    fn get_item(&self) -> Item {
+        let order = STATE.lock().unwrap().sort_order(&self.dir);
+        let mut item = Item::new("View Options");
+        item.subtitle = Some(format!("Sort by {}, hidden files, ...", match order.mode {
+            SortMode::Name => "name",
+            SortMode::Modified => "modification time",
+            SortMode::Size => "size",
+        }));
+        item.icon = Some(Icon::Character{ch: '\u{f0dc}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let (show_hidden, order) = {
+            let state = STATE.lock().unwrap();
+            (state.show_hidden, state.sort_order(&self.dir))
+        };
+        let settings = vec![
+            BrowserSetting::Sort(SortMode::Name),
+            BrowserSetting::Sort(SortMode::Modified),
+            BrowserSetting::Sort(SortMode::Size),
+            BrowserSetting::DirsFirst(!order.dirs_first),
+            BrowserSetting::ShowHidden(!show_hidden),
+        ];
+        Ok(settings.into_iter().enumerate().map(|(idx, setting)| {
+            let mut item = Item::new_action_item(Arc::new(Box::new(BrowserSettingAction {
+                dir: self.dir.clone(),
+                setting: setting,
+            })));
+            item.priority = idx as i32;
+            item
+        }).collect())
+    }
+}
+
+impl Action for BrowserSettingAction {
+    fn get_item(&self) -> Item {
+        let title = match self.setting {
+            BrowserSetting::Sort(SortMode::Name) => "Sort by Name",
+            BrowserSetting::Sort(SortMode::Modified) => "Sort by Modification Time",
+            BrowserSetting::Sort(SortMode::Size) => "Sort by Size",
+            BrowserSetting::DirsFirst(true) => "Show Directories First",
+            BrowserSetting::DirsFirst(false) => "Mix Directories and Files",
+            BrowserSetting::ShowHidden(true) => "Show Hidden Files",
+            BrowserSetting::ShowHidden(false) => "Hide Hidden Files",
+        };
+        let mut item = Item::new(title);
+        item.subtitle = Some(self.dir.to_string_lossy().into());
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        {
+            let mut state = STATE.lock().unwrap();
+            let mut order = state.sort_order(&self.dir);
+            match self.setting {
+                BrowserSetting::Sort(mode) => { order.mode = mode; },
+                BrowserSetting::DirsFirst(v) => { order.dirs_first = v; },
+                BrowserSetting::ShowHidden(v) => { state.show_hidden = v; },
             }
-            Ok(ret)
+            state.sort_orders.insert(self.dir.clone(), order);
+            state.save();
         }
+        FileBrowserEntry::list_dir(&self.dir)
     }
 }
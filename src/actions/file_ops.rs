@@ -0,0 +1,395 @@
+//! File operations on path items
+
+extern crate url;
+extern crate libc;
+
+use self::url::Url;
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::os::unix::fs::PermissionsExt;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::FileBrowserEntry;
+use actions::utils::trash;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileOperation {
+    CopyPath,
+    CopyURI,
+    Rename,
+    Duplicate,
+    Trash,
+    NewFolder,
+    NewFile,
+    CopyTo,
+    MoveTo,
+    ChangeMode,
+}
+
+/// Operation on path input
+pub struct FileOperationAction {
+    op: FileOperation,
+}
+
+/// Operation on `path` which requires text input, like new name
+struct TextInputAction {
+    op: FileOperation,
+    path: PathBuf,
+}
+
+/// Confirm moving `path` to trash
+struct ConfirmTrashAction {
+    path: PathBuf,
+}
+
+/// Browse for destination directory of copying or moving `src`
+struct DestinationAction {
+    op: FileOperation,
+    src: PathBuf,
+    dir: PathBuf,
+}
+
+/// Copy or move `src` into `dir`
+struct PasteAction {
+    op: FileOperation,
+    src: PathBuf,
+    dir: PathBuf,
+}
+
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+fn path_item(path: &Path) -> Result<Item, Box<Error + Send + Sync>> {
+    match FileBrowserEntry::new(file_name(path), path.to_path_buf()) {
+        Some(entry) => Ok(Item::new_action_item(Arc::new(Box::new(entry)))),
+        None => Err(Box::new(ActionError::new(&format!("Invalid path: {:?}", path)))),
+    }
+}
+
+/// Directory of `path`: itself if it's a directory, or its parent
+fn dir_of(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        path.parent().unwrap_or(Path::new("/")).to_path_buf()
+    }
+}
+
+/// Check that `name` is a plain file name and `dir/name` does not exist
+fn new_path(dir: &Path, name: &str) -> Result<PathBuf, Box<Error + Send + Sync>> {
+    let name = name.trim();
+    if name.len() == 0 || name == "." || name == ".." || name.contains('/') {
+        return Err(Box::new(ActionError::new(&format!("Invalid file name: {}", name))));
+    }
+    let ret = dir.join(name);
+    if fs::symlink_metadata(&ret).is_ok() {
+        return Err(Box::new(ActionError::new(&format!("File already exists: {:?}", ret))));
+    }
+    Ok(ret)
+}
+
+/// Copy file, symbolic link or directory recursively
+fn copy_recursive(src: &Path, dst: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(src)?;
+        ::std::os::unix::fs::symlink(target, dst)
+    } else if meta.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dst, meta.permissions())
+    } else {
+        fs::copy(src, dst).map(|_| ())
+    }
+}
+
+fn remove_recursive(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Move file, fallback to copy and remove across file systems
+fn move_path(src: &Path, dst: &Path) -> io::Result<()> {
+    if let Err(error) = fs::rename(src, dst) {
+        if error.raw_os_error() != Some(libc::EXDEV) {
+            return Err(error);
+        }
+        copy_recursive(src, dst)?;
+        remove_recursive(src)?;
+    }
+    Ok(())
+}
+
+/// Name for duplicate of `path`, like "foo copy.txt", "foo copy 2.txt"
+fn duplicate_path(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    let stem = path.file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or(String::new());
+    let ext = match path.extension() {
+        Some(ext) if !path.is_dir() => format!(".{}", ext.to_string_lossy()),
+        _ => String::new(),
+    };
+    let stem = if ext.len() == 0 { file_name(path) } else { stem };
+    let mut idx = 1;
+    loop {
+        let name = if idx == 1 { format!("{} copy{}", stem, ext) }
+                   else { format!("{} copy {}{}", stem, idx, ext) };
+        let ret = dir.join(name);
+        if fs::symlink_metadata(&ret).is_err() {
+            return ret;
+        }
+        idx += 1;
+    }
+}
+
+impl FileOperationAction {
+    pub fn get_all() -> Vec<FileOperationAction> {
+        vec![
+            FileOperation::CopyPath,
+            FileOperation::CopyURI,
+            FileOperation::Rename,
+            FileOperation::Duplicate,
+            FileOperation::Trash,
+            FileOperation::NewFolder,
+            FileOperation::NewFile,
+            FileOperation::CopyTo,
+            FileOperation::MoveTo,
+            FileOperation::ChangeMode,
+        ].into_iter().map(|op| FileOperationAction { op: op }).collect()
+    }
+}
+
+impl Action for FileOperationAction {
+    fn get_item(&self) -> Item {
+        let (title, ch) = match self.op {
+            FileOperation::CopyPath => ("Copy Path", '\u{f0c5}'),
+            FileOperation::CopyURI => ("Copy URI", '\u{f0c1}'),
+            FileOperation::Rename => ("Rename", '\u{f044}'),
+            FileOperation::Duplicate => ("Duplicate", '\u{f0c5}'),
+            FileOperation::Trash => ("Move to Trash", '\u{f1f8}'),
+            FileOperation::NewFolder => ("New Folder Here", '\u{f07b}'),
+            FileOperation::NewFile => ("New File Here", '\u{f016}'),
+            FileOperation::CopyTo => ("Copy To…", '\u{f0c5}'),
+            FileOperation::MoveTo => ("Move To…", '\u{f0b2}'),
+            FileOperation::ChangeMode => ("Change Permissions", '\u{f023}'),
+        };
+        let mut item = Item::new(title);
+        item.badge = Some("File Operation".into());
+        item.priority = 10;
+        item.icon = Some(Icon::Character{ch: ch, font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_path(&self) -> bool { true }
+
+    fn run_path(&self, path: &Path) -> ActionResult {
+        match self.op {
+            FileOperation::CopyPath => {
+                let mut item = Item::new_text_item(&path.to_string_lossy());
+                item.subtitle = Some("Path".into());
+                Ok(vec![item])
+            },
+            FileOperation::CopyURI => {
+                let uri = Url::from_file_path(path)
+                    .map_err(|_| ActionError::new(&format!("Invalid path: {:?}", path)))?;
+                let mut item = Item::new_text_item(uri.as_str());
+                item.subtitle = Some("URI".into());
+                Ok(vec![item])
+            },
+            FileOperation::Duplicate => {
+                let target = duplicate_path(path);
+                info!("Duplicate {:?} to {:?}", path, target);
+                copy_recursive(path, &target)?;
+                Ok(vec![path_item(&target)?])
+            },
+            FileOperation::Trash => {
+                Ok(vec![Item::new_action_item(Arc::new(Box::new(ConfirmTrashAction {
+                    path: path.to_path_buf(),
+                })))])
+            },
+            FileOperation::CopyTo | FileOperation::MoveTo => {
+                DestinationAction {
+                    op: self.op,
+                    src: path.to_path_buf(),
+                    dir: path.parent().unwrap_or(Path::new("/")).to_path_buf(),
+                }.run()
+            },
+            FileOperation::Rename | FileOperation::NewFolder |
+            FileOperation::NewFile | FileOperation::ChangeMode => {
+                Ok(vec![Item::new_action_item(Arc::new(Box::new(TextInputAction {
+                    op: self.op,
+                    path: path.to_path_buf(),
+                })))])
+            },
+        }
+    }
+}
+
+impl Action for TextInputAction {
+    fn get_item(&self) -> Item {
+        let (title, subtitle) = match self.op {
+            FileOperation::Rename => (format!("Rename {} to…", file_name(&self.path)),
+                                      "Enter new name".to_string()),
+            FileOperation::NewFolder => (format!("New Folder in {}", dir_of(&self.path).to_string_lossy()),
+                                         "Enter folder name".to_string()),
+            FileOperation::NewFile => (format!("New File in {}", dir_of(&self.path).to_string_lossy()),
+                                       "Enter file name".to_string()),
+            _ => {
+                let mode = fs::symlink_metadata(&self.path)
+                    .map(|x| x.permissions().mode() & 0o7777)
+                    .unwrap_or(0);
+                (format!("Change Permissions of {}", file_name(&self.path)),
+                 format!("Currently {:o}, enter octal mode like 644", mode))
+            },
+        };
+        let mut item = Item::new(&title);
+        item.subtitle = Some(subtitle);
+        item
+    }
+
+    fn accept_text(&self) -> bool { true }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        match self.op {
+            FileOperation::Rename => {
+                let target = new_path(&self.path.parent().unwrap_or(Path::new("/")), text)?;
+                info!("Rename {:?} to {:?}", self.path, target);
+                fs::rename(&self.path, &target)?;
+                Ok(vec![path_item(&target)?])
+            },
+            FileOperation::NewFolder => {
+                let target = new_path(&dir_of(&self.path), text)?;
+                info!("Create directory {:?}", target);
+                fs::create_dir(&target)?;
+                Ok(vec![path_item(&target)?])
+            },
+            FileOperation::NewFile => {
+                let target = new_path(&dir_of(&self.path), text)?;
+                info!("Create file {:?}", target);
+                fs::OpenOptions::new().write(true).create_new(true).open(&target)?;
+                Ok(vec![path_item(&target)?])
+            },
+            _ => {
+                let mode = u32::from_str_radix(text.trim(), 8)
+                    .map_err(|_| ActionError::new(&format!("Invalid mode: {}", text)))?;
+                if mode > 0o7777 {
+                    return Err(Box::new(ActionError::new(&format!("Invalid mode: {}", text))));
+                }
+                info!("Change mode of {:?} to {:o}", self.path, mode);
+                fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))?;
+                Ok(vec![path_item(&self.path)?])
+            },
+        }
+    }
+}
+
+impl Action for ConfirmTrashAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(&format!("Confirm: Move {} to Trash", file_name(&self.path)));
+        item.subtitle = Some(self.path.to_string_lossy().into());
+        item.icon = Some(Icon::Character{ch: '\u{f071}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run(&self) -> ActionResult {
+        info!("Move {:?} to trash", self.path);
+        trash::move_to_trash(&self.path)?;
+        Ok(Vec::new())
+    }
+}
+
+impl Action for DestinationAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(&file_name(&self.dir));
+        item.subtitle = Some(self.dir.to_string_lossy().into());
+        item.badge = Some("Directory".into());
+        item.icon = Some(Icon::Character{ch: '\u{f115}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let mut ret = Vec::new();
+
+        let mut here = Item::new_action_item(Arc::new(Box::new(PasteAction {
+            op: self.op,
+            src: self.src.clone(),
+            dir: self.dir.clone(),
+        })));
+        here.priority = -100;
+        ret.push(here);
+
+        if let Some(parent) = self.dir.parent() {
+            let mut item = Item::new_action_item(Arc::new(Box::new(DestinationAction {
+                op: self.op,
+                src: self.src.clone(),
+                dir: parent.to_path_buf(),
+            })));
+            item.title = "..".into();
+            item.priority = -90;
+            ret.push(item);
+        }
+
+        let mut subdirs : Vec<PathBuf> = self.dir.read_dir()?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .filter(|x| x.is_dir() && !file_name(x).starts_with('.'))
+            .collect();
+        subdirs.sort();
+        for dir in subdirs {
+            ret.push(Item::new_action_item(Arc::new(Box::new(DestinationAction {
+                op: self.op,
+                src: self.src.clone(),
+                dir: dir,
+            }))));
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for PasteAction {
+    fn get_item(&self) -> Item {
+        let verb = if self.op == FileOperation::MoveTo { "Move" } else { "Copy" };
+        let mut item = Item::new(&format!("{} {} Here", verb, file_name(&self.src)));
+        item.subtitle = Some(self.dir.to_string_lossy().into());
+        item.icon = Some(Icon::Character{ch: '\u{f00c}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let target = new_path(&self.dir, &file_name(&self.src))?;
+        if target.starts_with(&self.src) {
+            return Err(Box::new(ActionError::new("Cannot copy or move a directory into itself")));
+        }
+        if self.op == FileOperation::MoveTo {
+            info!("Move {:?} to {:?}", self.src, target);
+            move_path(&self.src, &target)?;
+        } else {
+            info!("Copy {:?} to {:?}", self.src, target);
+            copy_recursive(&self.src, &target)?;
+        }
+        Ok(vec![path_item(&target)?])
+    }
+}
@@ -0,0 +1,441 @@
+//! Search files by name under configured roots, using an on-disk index
+//! which is refreshed incrementally through inotify, and rebuilt when stale
+
+extern crate ignore;
+extern crate inotify;
+extern crate glob;
+
+use self::ignore::Match;
+use self::ignore::gitignore::{Gitignore, GitignoreBuilder};
+use self::inotify::{Inotify, WatchMask, WatchDescriptor, EventMask};
+
+use toml;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::io::prelude::*;
+
+use mcore::action::{Action, ActionResult};
+use mcore::item::{Item, Icon};
+use mcore::fuzzymatch::fuzzymatch;
+use actions::ActionError;
+use actions::file_browser::FileBrowserEntry;
+use actions::utils;
+
+/// Minimal interval between saving index file
+const SAVE_INTERVAL: u64 = 60;
+/// First line of index file, followed by the settings it's built with
+const INDEX_HEADER: &str = "# minions file index v1 ";
+
+#[derive(Deserialize, Clone)]
+struct Config {
+    roots: Vec<String>,
+    /// Glob patterns of file names or paths to ignore
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Respect .gitignore files
+    #[serde(default)]
+    gitignore: bool,
+    /// Index hidden files
+    #[serde(default)]
+    hidden: bool,
+    max_depth: Option<usize>,
+    max_results: Option<usize>,
+    /// Rescan when saved index is older than this, in hours, default to 24
+    rescan_interval: Option<u64>,
+}
+
+type Index = Arc<RwLock<BTreeSet<PathBuf>>>;
+
+pub struct FileSearchAction {
+    index: Index,
+    max_results: usize,
+}
+
+/// Root directory and settings to index
+#[derive(Clone)]
+struct Indexer {
+    roots: Vec<PathBuf>,
+    ignore: Vec<glob::Pattern>,
+    gitignore: bool,
+    /// Global gitignore (core.excludesFile), if gitignore is respected
+    global_gitignore: Option<Gitignore>,
+    hidden: bool,
+    max_depth: Option<usize>,
+    index_file: PathBuf,
+    rescan_interval: Duration,
+}
+
+impl Indexer {
+    fn is_ignored(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return false,
+        };
+        if !self.hidden && name.starts_with('.') {
+            return true;
+        }
+        let path_str = path.to_string_lossy();
+        self.ignore.iter().any(|p| p.matches(&name) || p.matches(&path_str)) ||
+            self.is_gitignored(path)
+    }
+
+    /// Whether path is ignored by git, like the walker of full scan does:
+    /// by .gitignore and .git/info/exclude in its ancestors (deeper first), then global gitignore
+    fn is_gitignored(&self, path: &Path) -> bool {
+        if !self.gitignore {
+            return false;
+        }
+        let is_dir = path.is_dir();
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            for file in [d.join(".gitignore"), d.join(".git/info/exclude")].iter() {
+                if !file.is_file() {
+                    continue;
+                }
+                let mut builder = GitignoreBuilder::new(d);
+                if let Some(error) = builder.add(file) {
+                    debug!("Error in {:?}: {}", file, error);
+                }
+                let matcher = match builder.build() {
+                    Ok(matcher) => matcher,
+                    Err(_) => continue,
+                };
+                match matcher.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {},
+                }
+            }
+            dir = d.parent();
+        }
+        match self.global_gitignore {
+            Some(ref matcher) => matcher.matched(path, is_dir).is_ignore(),
+            None => false,
+        }
+    }
+
+    /// Depth of path relative to its root, None if not under any root
+    fn depth_of(&self, path: &Path) -> Option<usize> {
+        self.roots.iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .map(|rel| rel.components().count())
+            .min()
+    }
+
+    /// Walk `dir` (at `depth` from its root) and return all paths under it
+    fn walk(&self, dir: &Path, depth: usize) -> Vec<PathBuf> {
+        let mut builder = ignore::WalkBuilder::new(dir);
+        builder.hidden(!self.hidden)
+               .git_ignore(self.gitignore)
+               .git_global(self.gitignore)
+               .git_exclude(self.gitignore)
+               .ignore(false)
+               .max_depth(self.max_depth.map(|x| if x > depth { x - depth } else { 0 }));
+        let ignore_patterns = self.ignore.clone();
+        builder.filter_entry(move |entry| {
+            let name = entry.file_name().to_string_lossy();
+            let path = entry.path().to_string_lossy();
+            !ignore_patterns.iter().any(|p| p.matches(&name) || p.matches(&path))
+        });
+        builder.build()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.into_path())
+            .collect()
+    }
+
+    fn full_scan(&self) -> BTreeSet<PathBuf> {
+        let start = Instant::now();
+        let mut ret = BTreeSet::new();
+        for root in self.roots.iter() {
+            ret.extend(self.walk(root, 0));
+        }
+        info!("Indexed {} files in {:?}", ret.len(), start.elapsed());
+        ret
+    }
+
+    /// Settings affecting the index, saved in index file
+    fn settings(&self) -> String {
+        let ignore : Vec<&str> = self.ignore.iter().map(|x| x.as_str()).collect();
+        format!("{:?} {:?} {} {} {:?}", self.roots, ignore, self.gitignore, self.hidden, self.max_depth)
+            .replace('\n', " ")
+    }
+
+    /// Load saved index, return it and whether it's fresh, i.e. no rescan is needed.
+    /// Index built with other settings is dropped
+    fn load(&self) -> (BTreeSet<PathBuf>, bool) {
+        let mut ret = BTreeSet::new();
+        let file = match File::open(&self.index_file) {
+            Ok(file) => file,
+            Err(_) => return (ret, false),
+        };
+        let age = file.metadata().and_then(|x| x.modified())
+            .ok().and_then(|x| x.elapsed().ok());
+        let mut lines = BufReader::new(file).lines();
+        if lines.next().and_then(|x| x.ok()) != Some(format!("{}{}", INDEX_HEADER, self.settings())) {
+            info!("File index is built with other settings, dropped");
+            return (ret, false);
+        }
+        for line in lines {
+            match line {
+                Ok(line) => { ret.insert(PathBuf::from(line)); },
+                Err(_) => return (BTreeSet::new(), false),
+            }
+        }
+        debug!("Loaded {} files from index {:?}", ret.len(), self.index_file);
+        let fresh = age.map(|x| x < self.rescan_interval).unwrap_or(false);
+        (ret, fresh)
+    }
+
+    /// Save index atomically
+    fn save(&self, index: &BTreeSet<PathBuf>) {
+        let tmp_file = self.index_file.with_extension("tmp");
+        let result = File::create(&tmp_file).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            writeln!(writer, "{}{}", INDEX_HEADER, self.settings())?;
+            for path in index.iter() {
+                if let Some(path) = path.to_str() {
+                    if !path.contains('\n') {
+                        writeln!(writer, "{}", path)?;
+                    }
+                }
+            }
+            writer.flush()
+        }).and_then(|_| fs::rename(&tmp_file, &self.index_file));
+        if let Err(error) = result {
+            warn!("Unable to save file index: {}", error);
+        }
+    }
+
+    fn add_watches(&self, inotify: &mut Inotify,
+                   watches: &mut HashMap<WatchDescriptor, PathBuf>,
+                   paths: &[PathBuf]) {
+        let mask = WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_FROM |
+                   WatchMask::MOVED_TO | WatchMask::ONLYDIR;
+        for path in paths.iter() {
+            if !path.is_dir() {
+                continue;
+            }
+            match inotify.add_watch(path, mask) {
+                Ok(wd) => { watches.insert(wd, path.clone()); },
+                Err(error) => warn!("Unable to watch {:?}: {}", path, error),
+            }
+        }
+    }
+
+    /// Index in background: full scan unless the loaded index is `fresh`,
+    /// then keep updated using inotify
+    fn run(self, index: Index, fresh: bool) {
+        if !fresh {
+            let scanned = self.full_scan();
+            self.save(&scanned);
+            *index.write().unwrap() = scanned;
+        }
+        let dirs : Vec<PathBuf> = index.read().unwrap().iter()
+            .filter(|x| x.is_dir()).cloned().collect();
+
+        let mut inotify = match Inotify::init() {
+            Ok(inotify) => inotify,
+            Err(error) => {
+                warn!("Unable to init inotify, file index would not be updated: {}", error);
+                return;
+            }
+        };
+        let mut watches = HashMap::new();
+        self.add_watches(&mut inotify, &mut watches, &dirs);
+        debug!("Watching {} directories for file index", watches.len());
+
+        let mut buffer = [0u8; 4096];
+        let mut last_save = Instant::now();
+        let mut dirty = false;
+        loop {
+            let events = match inotify.read_events_blocking(&mut buffer) {
+                Ok(events) => events,
+                Err(error) => {
+                    warn!("Error reading inotify events: {}", error);
+                    return;
+                }
+            };
+            let mut added = Vec::new();
+            let mut removed = Vec::new();
+            let mut overflow = false;
+            for event in events {
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    overflow = true;
+                    continue;
+                }
+                let dir = match watches.get(&event.wd) {
+                    Some(dir) => dir.clone(),
+                    None => continue,
+                };
+                let name = match event.name {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let path = dir.join(name);
+                if event.mask.contains(EventMask::CREATE) || event.mask.contains(EventMask::MOVED_TO) {
+                    added.push(path);
+                } else if event.mask.contains(EventMask::DELETE) || event.mask.contains(EventMask::MOVED_FROM) {
+                    removed.push(path);
+                }
+            }
+
+            if overflow {
+                warn!("inotify queue overflow, rescanning");
+                *index.write().unwrap() = self.full_scan();
+                dirty = true;
+            }
+
+            for path in removed {
+                trace!("File index remove: {:?}", path);
+                let mut index = index.write().unwrap();
+                let subtree : Vec<PathBuf> = index.range(path.clone()..)
+                    .take_while(|x| x.starts_with(&path))
+                    .cloned().collect();
+                for x in subtree {
+                    index.remove(&x);
+                }
+                watches.retain(|_, dir| !dir.starts_with(&path));
+                dirty = true;
+            }
+
+            for path in added {
+                if self.is_ignored(&path) {
+                    continue;
+                }
+                let depth = match self.depth_of(&path) {
+                    Some(depth) => depth,
+                    None => continue,
+                };
+                if self.max_depth.map(|x| depth > x).unwrap_or(false) {
+                    continue;
+                }
+                trace!("File index add: {:?}", path);
+                let paths = if path.is_dir() {
+                    self.walk(&path, depth)
+                } else {
+                    vec![path]
+                };
+                self.add_watches(&mut inotify, &mut watches, &paths);
+                index.write().unwrap().extend(paths);
+                dirty = true;
+            }
+
+            if dirty && last_save.elapsed() >= Duration::from_secs(SAVE_INTERVAL) {
+                self.save(&index.read().unwrap());
+                last_save = Instant::now();
+                dirty = false;
+            }
+        }
+    }
+}
+
+impl FileSearchAction {
+    pub fn new(config: toml::Value) -> Option<FileSearchAction> {
+        let config = match config.try_into::<Config>() {
+            Ok(config) => config,
+            Err(error) => {
+                warn!("Error loading file search config: {}", error);
+                return None;
+            }
+        };
+
+        let indexer = Indexer {
+            roots: config.roots.iter()
+                   .map(|x| utils::expand_path(x))
+                   .filter(|x| x.is_dir())
+                   .collect(),
+            ignore: config.ignore.iter()
+                    .filter_map(|x| match glob::Pattern::new(x) {
+                        Ok(p) => Some(p),
+                        Err(error) => {
+                            warn!("Invalid file search ignore pattern {}: {}", x, error);
+                            None
+                        }
+                    })
+                    .collect(),
+            gitignore: config.gitignore,
+            global_gitignore: if config.gitignore { Some(Gitignore::global().0) } else { None },
+            hidden: config.hidden,
+            max_depth: config.max_depth,
+            index_file: utils::minions_dir().join("file_index"),
+            rescan_interval: Duration::from_secs(config.rescan_interval.unwrap_or(24) * 3600),
+        };
+        if indexer.roots.len() == 0 {
+            warn!("No valid file search root");
+            return None;
+        }
+
+        // serve results from saved index, until the rescan is done if it's stale
+        let (loaded, fresh) = indexer.load();
+        let index = Arc::new(RwLock::new(loaded));
+        let index_ = index.clone();
+        thread::Builder::new()
+            .name("file-search-indexer".into())
+            .spawn(move || indexer.run(index_, fresh))
+            .unwrap();
+
+        Some(FileSearchAction {
+            index: index,
+            max_results: config.max_results.unwrap_or(50),
+        })
+    }
+}
+
+impl Action for FileSearchAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("File Search");
+        item.subtitle = Some("Search indexed files by name".into());
+        item.priority = -10;
+        item.icon = Some(Icon::Character{ch: '\u{f002}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_text(&self) -> bool { true }
+
+    fn is_search_provider(&self) -> bool { true }
+
+    fn search(&self, text: &str) -> ActionResult {
+        self.run_text(text)
+    }
+
+    fn search_uses_query(&self) -> bool { true }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        let index = self.index.read().unwrap();
+        let mut scores : Vec<(i32, &PathBuf)> = index.iter()
+            .filter_map(|path| {
+                let score = fuzzymatch(&path.to_string_lossy(), text, false);
+                if score > 0 { Some((score, path)) } else { None }
+            })
+            .collect();
+        if scores.len() == 0 {
+            return Err(Box::new(ActionError::new(&format!("No file matching {}", text))));
+        }
+        scores.sort_by_key(|x| (-x.0, x.1.as_os_str().len()));
+
+        Ok(scores.into_iter()
+           .filter_map(|(_, path)| {
+               let name = match path.file_name() {
+                   Some(name) => name.to_string_lossy().into_owned(),
+                   None => path.to_string_lossy().into_owned(),
+               };
+               FileBrowserEntry::new(name, path.clone()).map(|entry| (path, entry))
+           })
+           .take(self.max_results)
+           .enumerate()
+           .map(|(idx, (path, entry))| {
+               let mut item = Item::new_action_item(Arc::new(Box::new(entry)));
+               item.priority = idx as i32;
+               // matched by the whole path, also when searched globally
+               item.search_str = Some(path.to_string_lossy().into_owned());
+               item
+           })
+           .collect())
+    }
+}
@@ -15,18 +15,25 @@ use toml;
 use std::ffi::OsStr;
 use std::error::Error;
 use std::path::{Path, PathBuf};
-use mcore::action::{Action, ActionResult};
+use mcore::action::{Action, ActionResult, MimeSupport};
 use mcore::item::{Item, ItemData, Icon};
 use actions::ActionError;
-use actions::utils::subprocess;
+use mcore::{xdg, mime};
+use actions::utils::{subprocess, terminal};
 
 #[derive(Debug)]
 pub struct LinuxDesktopEntry {
+    /// Desktop file ID, i.e. file name without `.desktop`
+    id: String,
     name: String,
     comment: Option<String>,
     exec: Vec<String>,
     icon_text: Option<String>,
     terminal: bool,
+    /// Supported MIME types
+    mime_types: Vec<String>,
+    /// Whether to accept text input as extra arguments
+    accepts_text: bool,
 }
 
 impl Action for LinuxDesktopEntry {
@@ -56,10 +63,32 @@ impl Action for LinuxDesktopEntry {
 
     fn accept_nothing(&self) -> bool { true }
 
+    fn accept_text(&self) -> bool { self.accepts_text }
+
     fn accept_path(&self) -> bool {
         self.exec.iter().find(|arg| (*arg == "%f" || *arg == "%F")).is_some()
     }
 
+    fn mime_support(&self, path_mime: &str) -> MimeSupport {
+        let desktop_file = format!("{}.desktop", self.id);
+        if mime::default_application(path_mime).as_ref() == Some(&desktop_file) {
+            return MimeSupport::Default;
+        }
+        // other applications in mimeapps.list are preferred but not the default
+        if mime::default_applications(path_mime).contains(&desktop_file) {
+            return MimeSupport::Supported;
+        }
+        if self.mime_types.len() == 0 {
+            return MimeSupport::Unknown;
+        }
+        let ancestors = mime::ancestors(path_mime);
+        if self.mime_types.iter().any(|t| ancestors.iter().any(|m| mime::matches(t, m))) {
+            MimeSupport::Supported
+        } else {
+            MimeSupport::Unsupported
+        }
+    }
+
     fn run_path(&self, path: &Path) -> ActionResult {
         self.run_path_or_empty(Some(path))
     }
@@ -68,46 +97,99 @@ impl Action for LinuxDesktopEntry {
         self.run_path_or_empty(None)
     }
 
+    fn run_text(&self, text: &str) -> ActionResult {
+        let args = shlex::split(text).ok_or(ActionError::new("Invalid arguments"))?;
+        self.launch_with_args(&args)?;
+        Ok(Vec::new())
+    }
+
     fn should_return_items(&self) -> bool { false }
 }
 
 #[derive(Deserialize)]
 struct Config {
     directories: Vec<String>,
+    /// Accept text input as extra arguments
+    #[serde(default)]
+    text_arguments: bool,
+    /// Desktop file IDs not accepting text input
+    #[serde(default)]
+    text_arguments_disabled: Vec<String>,
 }
 
 impl LinuxDesktopEntry {
 
     fn run_path_or_empty(&self, path: Option<&Path>) -> ActionResult {
+        let target = match path {
+            Some(p) => p.to_str(),
+            None => None,
+        };
+        self.launch(target)?;
+        Ok(Vec::new())
+    }
+
+    /// Launch this application, with optional target file path or URL
+    pub fn launch(&self, target: Option<&str>) -> Result<(), Box<Error + Send + Sync>> {
+        let args : Vec<String> = target.into_iter().map(|x| x.to_string()).collect();
+        self.launch_with_args(&args)
+    }
+
+    /// Launch this application with arguments
+    fn launch_with_args(&self, args: &[String]) -> Result<(), Box<Error + Send + Sync>> {
+        let cmd = self.command(args)?;
+        let cmd : Vec<&str> = cmd.iter().map(|x| x.as_str()).collect();
+        subprocess::spawn_app(&self.id, cmd[0], &cmd[1..])
+    }
+
+    /// Command line with arguments,
+    /// which replace the file or URL field code in Exec, or are appended if there's none
+    fn command(&self, args: &[String]) -> Result<Vec<String>, Box<Error + Send + Sync>> {
         if self.exec.len() <= 0 {
             return Err(Box::new(ActionError::new("Executable path is empty")));
         }
 
-        let mut cmd : Vec<&str> = Vec::new();
-        if self.terminal {
-            cmd.push("sh");
-            cmd.push("-c");
-            cmd.push(include_str!("./utils/sensible-terminal.sh"));
-            cmd.push("sensible-terminal.sh");
-            cmd.push("-e");
-        }
-
+        let mut cmd : Vec<String> = Vec::new();
+        let mut args_used = false;
         for arg in self.exec.iter() {
-            if *arg == "%f" || *arg == "%F" {
-                if let Some(p) = path {
-                    if let Some(p) = p.to_str() {
-                        cmd.push(p);
-                    }
+            if *arg == "%f" || *arg == "%F" || *arg == "%u" || *arg == "%U" {
+                if !args_used {
+                    cmd.extend(args.iter().cloned());
+                    args_used = true;
                 }
-            } else if *arg == "%u" || *arg == "%U" {
+            } else if *arg == "%c" {
+                cmd.push(self.name.clone());
+            } else if *arg == "%i" || *arg == "%k" {
                 // nop
             } else {
-                cmd.push(&arg);
+                cmd.push(arg.clone());
             }
         }
+        if !args_used {
+            cmd.extend(args.iter().cloned());
+        }
+        if cmd.len() == 0 {
+            return Err(Box::new(ActionError::new("Executable path is empty")));
+        }
 
-        subprocess::spawn(cmd[0], &cmd[1..])?;
-        Ok(Vec::new())
+        if self.terminal {
+            cmd = terminal::wrap_command(&cmd)?;
+        }
+        Ok(cmd)
+    }
+
+    /// Find desktop entry by desktop file ID in XDG data directories
+    pub fn find(id: &str) -> Option<LinuxDesktopEntry> {
+        let filename = if id.ends_with(".desktop") { id.to_string() } else { format!("{}.desktop", id) };
+        for dir in xdg::data_dirs() {
+            let filepath = dir.join("applications").join(&filename);
+            if filepath.is_file() {
+                match LinuxDesktopEntry::get(&filepath) {
+                    Ok(entry) => return Some(entry),
+                    Err(error) => warn!("Unable to load desktop entry {:?}: {}", filepath, error),
+                }
+            }
+        }
+        None
     }
 
 
@@ -122,7 +204,13 @@ impl LinuxDesktopEntry {
 
         let exec_str = config.get_from(Some("Desktop Entry"), "Exec").ok_or(err.clone())?;
 
+        let id = match filepath.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => return Err(Box::new(ActionError::new("Invalid desktop entry file name"))),
+        };
+
         Ok(LinuxDesktopEntry {
+            id: id,
             name: config.get_from(Some("Desktop Entry"), "Name").ok_or(err.clone())?.into(),
             comment: Some(config.get_from_or(Some("Desktop Entry"), "Comment", "").into()),
             exec: shlex::split(exec_str).ok_or(err.clone())?,
@@ -131,6 +219,12 @@ impl LinuxDesktopEntry {
                 None => None,
             },
             terminal: config.get_from_or(Some("Desktop Entry"), "Terminal", "false") == "true",
+            mime_types: config.get_from_or(Some("Desktop Entry"), "MimeType", "")
+                        .split(';')
+                        .filter(|x| x.len() > 0)
+                        .map(|x| x.into())
+                        .collect(),
+            accepts_text: false,
         })
     }
 
@@ -157,7 +251,11 @@ impl LinuxDesktopEntry {
                         continue;
                     }
                     match LinuxDesktopEntry::get(&entry_path) {
-                        Ok(item) => ret.push(item),
+                        Ok(mut item) => {
+                            item.accepts_text = config.text_arguments &&
+                                !config.text_arguments_disabled.contains(&item.id);
+                            ret.push(item);
+                        },
                         Err(_) => (),
                     }
                 }
@@ -168,3 +266,34 @@ impl LinuxDesktopEntry {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use actions::linux_desktop_entry::LinuxDesktopEntry;
+
+    fn entry(exec: &[&str]) -> LinuxDesktopEntry {
+        LinuxDesktopEntry {
+            id: "test".into(),
+            name: "Test".into(),
+            comment: None,
+            exec: exec.iter().map(|x| x.to_string()).collect(),
+            icon_text: None,
+            terminal: false,
+            mime_types: Vec::new(),
+            accepts_text: true,
+        }
+    }
+
+    #[test]
+    fn command_test() {
+        let args = vec!["--private-window".to_string(), "a b".to_string()];
+        // appended without field codes
+        assert_eq!(entry(&["foo", "--new"]).command(&args).unwrap(),
+                   vec!["foo", "--new", "--private-window", "a b"]);
+        // replacing the field code
+        assert_eq!(entry(&["foo", "%U", "--new"]).command(&args).unwrap(),
+                   vec!["foo", "--private-window", "a b", "--new"]);
+        assert_eq!(entry(&["foo", "%c", "%i"]).command(&[]).unwrap(), vec!["foo", "Test"]);
+        assert!(entry(&[]).command(&args).is_err());
+    }
+}
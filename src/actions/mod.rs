@@ -7,13 +7,28 @@
 
 mod utils;
 
+// used by mcore and the frontends
+pub use self::utils::{clipboard_backend, paste, expand_path};
+
 mod linux_desktop_entry;
 mod search_engine;
 mod file_browser;
+mod archive;
+mod recent_files;
+mod dir_jump;
+mod content_search;
+mod file_search;
+mod file_ops;
+mod trash_browser;
+mod devices;
 mod custom_script;
 mod youdao;
+mod text_transform;
+mod open_with;
+mod open_url;
+mod fallback;
 
-#[cfg(feature="use-gtk")]
+mod clipboard_store;
 mod clipboard;
 
 use toml;
@@ -48,12 +63,42 @@ impl fmt::Display for ActionError {
 
 use mcore::action::Action;
 
+/// Actions for fallback items, which take the filter query when nothing else matches
+pub fn get_fallback_actions(config: &toml::Value) -> Vec<Arc<Box<Action + Sync + Send>>> {
+    match config.get("fallback") {
+        Some(opts) => fallback::get_all(opts.clone(), config.get("search_engine").cloned()),
+        None => Vec::new(),
+    }
+}
+
 pub fn get_actions(config: toml::Value) -> Vec<Arc<Box<Action + Sync + Send>>> {
     let mut ret : Vec<Arc<Box<Action + Sync + Send>>> = vec![];
+    if let Some(opts) = config.get("launcher") {
+        utils::subprocess::configure(opts.clone());
+    }
+    if let Some(opts) = config.get("terminal") {
+        utils::terminal::configure(opts.clone());
+    }
+    if let Some(opts) = config.get("content_search") {
+        content_search::configure(opts.clone());
+    }
+    if let Some(opts) = config.get("opener") {
+        utils::open::configure(opts.clone());
+    }
+    if let Some(opts) = config.get("clipboard") {
+        utils::clipboard_backend::configure(opts.clone());
+    }
+    if let Some(opts) = config.get("paste") {
+        utils::paste::configure(opts.clone());
+    }
     if let Some(opts) = config.get("linux_desktop_entry") {
+        let mut applications : Vec<Arc<Box<Action + Sync + Send>>> = vec![];
         for desktop_entry in linux_desktop_entry::LinuxDesktopEntry::get_all(opts.clone()) {
-            ret.push(Arc::new(Box::new(desktop_entry)));
+            applications.push(Arc::new(Box::new(desktop_entry)));
         }
+        open_with::set_applications(applications.clone());
+        ret.push(Arc::new(Box::new(open_with::OpenWithAction{})));
+        ret.extend(applications);
     }
     if let Some(opts) = config.get("search_engine") {
         for se in search_engine::SearchEngine::get_all(opts.clone()) {
@@ -66,14 +111,39 @@ pub fn get_actions(config: toml::Value) -> Vec<Arc<Box<Action + Sync + Send>>> {
         }
     }
 
+    if let Some(opts) = config.get("recent_files") {
+        for x in recent_files::get_all(opts.clone()) {
+            ret.push(Arc::new(x));
+        }
+    }
+
+    if let Some(opts) = config.get("dir_jump") {
+        ret.push(Arc::new(Box::new(dir_jump::DirJumpAction::new(opts.clone()))));
+    }
+    ret.push(Arc::new(Box::new(dir_jump::TerminalHereAction{})));
+    ret.push(Arc::new(Box::new(content_search::ContentSearchAction{})));
+
+    for x in file_ops::FileOperationAction::get_all() {
+        ret.push(Arc::new(Box::new(x)));
+    }
+    ret.push(Arc::new(Box::new(trash_browser::TrashAction{})));
+    ret.push(Arc::new(Box::new(devices::DevicesAction{})));
+
+    if let Some(opts) = config.get("file_search") {
+        if let Some(action) = file_search::FileSearchAction::new(opts.clone()) {
+            ret.push(Arc::new(Box::new(action)));
+        }
+    }
+
+    ret.push(Arc::new(Box::new(open_url::OpenUrlAction{})));
     ret.push(Arc::new(Box::new(youdao::Youdao{})));
+    for x in text_transform::TextTransformAction::get_all() {
+        ret.push(Arc::new(Box::new(x)));
+    }
 
-    if cfg!(feature="use-gtk") {
-        if let Some(opts) = config.get("clipboard_history") {
-            if let Some(max_len) = opts["max_entries"].as_integer() {
-                let action = clipboard::ClipboardHistoryAction::new(max_len as usize);
-                ret.push(Arc::new(Box::new(action)));
-            }
+    if let Some(opts) = config.get("clipboard_history") {
+        for x in clipboard::ClipboardHistoryAction::get_all(opts.clone()) {
+            ret.push(Arc::new(x));
         }
     }
 
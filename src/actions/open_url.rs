@@ -0,0 +1,50 @@
+//! Open quick-sent URL in browser, or email address in mail client
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionArg, ActionResult, ContentSupport};
+use mcore::content::{self, ContentKind};
+use actions::utils::open;
+
+pub struct OpenUrlAction {}
+
+impl Action for OpenUrlAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open URL");
+        item.subtitle = Some("Open in browser, or compose email".into());
+        item.badge = Some("URL".into());
+        item.icon = Some(Icon::Character{ch: '\u{f0ac}', font: "FontAwesome".into()});
+        item
+    }
+
+    /// Only URLs or email addresses, so it's never listed without quick-sent text
+    fn accept_arg(&self, arg: &ActionArg) -> bool {
+        match arg {
+            &ActionArg::Text(ref text) => {
+                let kind = content::classify(text);
+                kind == ContentKind::Url || kind == ContentKind::Email
+            },
+            _ => false,
+        }
+    }
+
+    fn content_support(&self, kind: ContentKind) -> ContentSupport {
+        match kind {
+            ContentKind::Url | ContentKind::Email => ContentSupport::Supported,
+            _ => ContentSupport::Unsupported,
+        }
+    }
+
+    fn should_return_items(&self) -> bool { false }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        let text = text.trim();
+        let url = match content::classify(text) {
+            ContentKind::Email if !text.starts_with("mailto:") => format!("mailto:{}", text),
+            ContentKind::Url if text.starts_with("www.") => format!("http://{}", text),
+            _ => text.to_string(),
+        };
+        info!("open: {}", url);
+        open::that(&url)?;
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,70 @@
+//! "Open with" chooser, listing applications able to open some file
+
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionArg, ActionResult, MimeSupport};
+use mcore::mime;
+use actions::ActionError;
+
+lazy_static! {
+    /// Candidate applications
+    static ref APPLICATIONS: RwLock<Vec<Arc<Box<Action + Sync + Send>>>> = RwLock::new(Vec::new());
+}
+
+/// Set candidate applications, those accepting paths are kept
+pub fn set_applications(applications: Vec<Arc<Box<Action + Sync + Send>>>) {
+    *APPLICATIONS.write().unwrap() = applications.into_iter().filter(|x| x.accept_path()).collect();
+}
+
+/// Applications able to open `path`, the default one first
+pub fn list(path: &Path) -> ActionResult {
+    let path_mime = mime::guess(path);
+    debug!("Open with: {:?} of type {}", path, path_mime);
+
+    let mut candidates : Vec<(MimeSupport, Item)> = APPLICATIONS.read().unwrap().iter()
+        .map(|app| {
+            let support = app.mime_support(&path_mime);
+            let mut item = Item::new_action_item(app.clone());
+            item.action_arg = ActionArg::Path(path.to_path_buf());
+            if support == MimeSupport::Default {
+                item.badge = Some("Default".into());
+            }
+            (support, item)
+        })
+        .collect();
+    let has_supported = candidates.iter().any(|x| x.0 <= MimeSupport::Supported);
+    if has_supported {
+        candidates.retain(|x| x.0 <= MimeSupport::Supported);
+    }
+    if candidates.len() == 0 {
+        return Err(Box::new(ActionError::new(&format!("No application for {}", path_mime))));
+    }
+
+    candidates.sort_by_key(|x| x.0);
+    Ok(candidates.into_iter().enumerate().map(|(idx, x)| {
+        // keep order after sorting by priority
+        let mut item = x.1;
+        item.priority = idx as i32;
+        item
+    }).collect())
+}
+
+pub struct OpenWithAction {}
+
+impl Action for OpenWithAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Open With…");
+        item.subtitle = Some("Choose application to open this file".into());
+        item.priority = -60;
+        item.icon = Some(Icon::Character{ch: '\u{f08e}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_path(&self) -> bool { true }
+
+    fn run_path(&self, path: &Path) -> ActionResult {
+        list(path)
+    }
+}
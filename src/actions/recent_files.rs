@@ -0,0 +1,309 @@
+//! Recently used files (recently-used.xbel) and GTK bookmarks
+
+extern crate url;
+extern crate chrono;
+
+use self::url::Url;
+use self::chrono::{DateTime, Local, FixedOffset};
+
+use toml;
+
+use std::fs::{self, File};
+use std::error::Error;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::FileBrowserEntry;
+use mcore::xdg;
+
+#[derive(Deserialize)]
+struct Config {
+    max_entries: Option<usize>,
+    #[serde(default)]
+    bookmarks: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RecentFile {
+    path: PathBuf,
+    modified: Option<DateTime<FixedOffset>>,
+    /// Application which most recently used it
+    application: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Bookmark {
+    path: PathBuf,
+    label: Option<String>,
+}
+
+/// Content parsed from `path`, reparsed when its modification time changes
+struct Cached<T> {
+    path: PathBuf,
+    parse: fn(&str) -> Vec<T>,
+    value: Mutex<Option<(SystemTime, Arc<Vec<T>>)>>,
+}
+
+impl<T> Cached<T> {
+    fn new(path: PathBuf, parse: fn(&str) -> Vec<T>) -> Cached<T> {
+        Cached { path: path, parse: parse, value: Mutex::new(None) }
+    }
+
+    fn get(&self) -> Result<Arc<Vec<T>>, Box<Error + Send + Sync>> {
+        let mtime = fs::metadata(&self.path)?.modified()?;
+        let mut value = self.value.lock().unwrap();
+        if let Some((ref cached_mtime, ref items)) = *value {
+            if *cached_mtime == mtime {
+                return Ok(items.clone());
+            }
+        }
+        debug!("Reloading {:?}", self.path);
+        let mut content = String::new();
+        File::open(&self.path)?.read_to_string(&mut content)?;
+        let items = Arc::new((self.parse)(&content));
+        *value = Some((mtime, items.clone()));
+        Ok(items)
+    }
+}
+
+pub struct RecentFilesAction {
+    recent: Cached<RecentFile>,
+    max_entries: usize,
+}
+
+pub struct BookmarksAction {
+    bookmarks: Cached<Bookmark>,
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+     .replace("&gt;", ">")
+     .replace("&quot;", "\"")
+     .replace("&apos;", "'")
+     .replace("&amp;", "&")
+}
+
+/// Value of attribute `name` in tag content `tag`
+fn xml_attr(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!(" {}=\"", name);
+    let start = match tag.find(&pattern) {
+        Some(pos) => pos + pattern.len(),
+        None => return None,
+    };
+    tag[start..].find('"').map(|end| unescape_xml(&tag[start..start + end]))
+}
+
+/// Local path of `file://` URI, with percent decoding
+fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    match Url::parse(uri) {
+        Ok(ref url) if url.scheme() == "file" => url.to_file_path().ok(),
+        _ => None,
+    }
+}
+
+/// Parse recently-used.xbel, newest first
+fn parse_xbel(content: &str) -> Vec<RecentFile> {
+    let mut ret = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<bookmark ") {
+        rest = &rest[start..];
+        let end = rest.find("</bookmark>").unwrap_or(rest.len());
+        let block = &rest[..end];
+        rest = &rest[end..];
+
+        let tag_end = block.find('>').unwrap_or(block.len());
+        let tag = &block[..tag_end];
+        let path = match xml_attr(tag, "href").and_then(|x| file_uri_to_path(&x)) {
+            Some(path) => path,
+            None => continue,
+        };
+        let parse_time = |x: String| DateTime::parse_from_rfc3339(&x).ok();
+        let modified = xml_attr(tag, "modified").and_then(&parse_time)
+            .or(xml_attr(tag, "added").and_then(&parse_time));
+
+        // the application which used it most recently
+        let application = block.split("<bookmark:application ").skip(1)
+            .map(|x| (xml_attr(x, "modified").and_then(&parse_time), xml_attr(x, "name")))
+            .max_by_key(|x| x.0)
+            .and_then(|x| x.1);
+
+        ret.push(RecentFile {
+            path: path,
+            modified: modified,
+            application: application,
+        });
+    }
+    ret.sort_by(|a, b| b.modified.cmp(&a.modified));
+    ret
+}
+
+/// Parse GTK bookmarks, each line is an URI optionally followed by a label
+fn parse_bookmarks(content: &str) -> Vec<Bookmark> {
+    content.lines().filter_map(|line| {
+        let line = line.trim();
+        let (uri, label) = match line.find(' ') {
+            Some(pos) => (&line[..pos], Some(line[pos + 1..].trim().to_string())),
+            None => (line, None),
+        };
+        file_uri_to_path(uri).map(|path| Bookmark {
+            path: path,
+            label: label,
+        })
+    }).collect()
+}
+
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// GTK 3 bookmarks, or the legacy ~/.gtk-bookmarks
+fn bookmarks_path() -> PathBuf {
+    let path = xdg::config_home().join("gtk-3.0/bookmarks");
+    if path.exists() {
+        return path;
+    }
+    let legacy = ::std::env::home_dir().unwrap_or(PathBuf::from("/")).join(".gtk-bookmarks");
+    if legacy.exists() { legacy } else { path }
+}
+
+pub fn get_all(config: toml::Value) -> Vec<Box<Action + Sync + Send>> {
+    let config = match config.try_into::<Config>() {
+        Ok(config) => config,
+        Err(error) => {
+            warn!("Error loading recent files config: {}", error);
+            return Vec::new();
+        }
+    };
+    let mut ret : Vec<Box<Action + Sync + Send>> = vec![
+        Box::new(RecentFilesAction {
+            recent: Cached::new(xdg::data_home().join("recently-used.xbel"), parse_xbel),
+            max_entries: config.max_entries.unwrap_or(50),
+        }),
+    ];
+    if config.bookmarks {
+        ret.push(Box::new(BookmarksAction {
+            bookmarks: Cached::new(bookmarks_path(), parse_bookmarks),
+        }));
+    }
+    ret
+}
+
+impl Action for RecentFilesAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Recent Files");
+        item.subtitle = Some("Recently used files".into());
+        item.icon = Some(Icon::Character{ch: '\u{f017}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let recent = self.recent.get()?;
+        let ret : Vec<Item> = recent.iter()
+            .filter(|x| fs::symlink_metadata(&x.path).is_ok())
+            .filter_map(|x| FileBrowserEntry::new(file_name(&x.path), x.path.clone()).map(|e| (x, e)))
+            .take(self.max_entries)
+            .enumerate()
+            .map(|(idx, (x, entry))| {
+                let mut item = Item::new_action_item(Arc::new(Box::new(entry)));
+                let mut subtitle = Vec::new();
+                if let Some(ref app) = x.application {
+                    subtitle.push(app.clone());
+                }
+                if let Some(ref modified) = x.modified {
+                    subtitle.push(modified.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string());
+                }
+                subtitle.push(x.path.to_string_lossy().into_owned());
+                item.subtitle = Some(subtitle.join("  "));
+                item.priority = idx as i32;
+                item
+            })
+            .collect();
+        if ret.len() == 0 {
+            return Err(Box::new(ActionError::new("No recent files")));
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for BookmarksAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Bookmarks");
+        item.subtitle = Some("GTK bookmarked directories".into());
+        item.icon = Some(Icon::Character{ch: '\u{f02e}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let bookmarks = self.bookmarks.get()?;
+        let ret : Vec<Item> = bookmarks.iter()
+            .filter(|x| fs::symlink_metadata(&x.path).is_ok())
+            .filter_map(|x| {
+                let name = x.label.clone().unwrap_or(file_name(&x.path));
+                FileBrowserEntry::new(name, x.path.clone())
+            })
+            .enumerate()
+            .map(|(idx, entry)| {
+                let mut item = Item::new_action_item(Arc::new(Box::new(entry)));
+                item.priority = idx as i32;
+                item
+            })
+            .collect();
+        if ret.len() == 0 {
+            return Err(Box::new(ActionError::new("No bookmarks")));
+        }
+        Ok(ret)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use actions::recent_files::{parse_xbel, parse_bookmarks};
+
+    #[test]
+    fn parse_xbel_test() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0" xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks">
+  <bookmark href="file:///tmp/old.txt" added="2017-07-01T10:00:00Z" modified="2017-07-01T10:00:00Z" visited="2017-07-01T10:00:00Z">
+    <info><metadata owner="http://freedesktop.org"><bookmark:applications>
+      <bookmark:application name="gedit" exec="&apos;gedit %u&apos;" modified="2017-07-01T10:00:00Z" count="1"/>
+    </bookmark:applications></metadata></info>
+  </bookmark>
+  <bookmark href="file:///tmp/a%20b.txt" added="2017-07-02T10:00:00Z" modified="2017-07-03T10:00:00.123456Z" visited="2017-07-02T10:00:00Z">
+    <info><metadata owner="http://freedesktop.org"><bookmark:applications>
+      <bookmark:application name="gedit" exec="&apos;gedit %u&apos;" modified="2017-07-02T10:00:00Z" count="1"/>
+      <bookmark:application name="Text &amp; Code" exec="&apos;code %u&apos;" modified="2017-07-03T10:00:00Z" count="2"/>
+    </bookmark:applications></metadata></info>
+  </bookmark>
+  <bookmark href="https://example.com/" added="2017-07-04T10:00:00Z"></bookmark>
+</xbel>"#;
+        let recent = parse_xbel(content);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].path, PathBuf::from("/tmp/a b.txt"));
+        assert_eq!(recent[0].application, Some("Text & Code".into()));
+        assert_eq!(recent[1].path, PathBuf::from("/tmp/old.txt"));
+        assert_eq!(recent[1].application, Some("gedit".into()));
+    }
+
+    #[test]
+    fn parse_bookmarks_test() {
+        let bookmarks = parse_bookmarks("file:///home/user/My%20Documents Docs\nfile:///tmp\nsftp://host/dir Remote\n");
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].path, PathBuf::from("/home/user/My Documents"));
+        assert_eq!(bookmarks[0].label, Some("Docs".into()));
+        assert_eq!(bookmarks[1].label, None);
+    }
+}
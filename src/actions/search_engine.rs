@@ -10,7 +10,8 @@ extern crate url;
 use self::url::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
 use toml;
 
-use mcore::action::{Action, ActionResult};
+use mcore::action::{Action, ActionResult, ContentSupport};
+use mcore::content::ContentKind;
 use mcore::item::{Item, Icon};
 use actions::utils::open;
 
@@ -33,6 +34,10 @@ impl Action for SearchEngine {
 
     fn accept_text(&self) -> bool { true }
 
+    fn content_support(&self, kind: ContentKind) -> ContentSupport {
+        if kind == ContentKind::Text { ContentSupport::Supported } else { ContentSupport::Unknown }
+    }
+
     fn run_text(&self, text: &str) -> ActionResult {
         let text = utf8_percent_encode(text, DEFAULT_ENCODE_SET).to_string();
         let url = self.address.replace("%s", &text);
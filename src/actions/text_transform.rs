@@ -0,0 +1,322 @@
+//! Transform quick-sent text: case, lines, encodings, formats, counts and hashes
+
+extern crate url;
+extern crate crypto;
+extern crate htmlescape;
+extern crate serde_json;
+
+use self::crypto::digest::Digest;
+use self::url::form_urlencoded;
+use self::url::percent_encoding::percent_decode;
+
+use toml;
+
+use std::collections::HashSet;
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionArg, ActionResult, ContentSupport};
+use mcore::content::ContentKind;
+use actions::ActionError;
+
+const BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transform {
+    UpperCase,
+    LowerCase,
+    TitleCase,
+    Trim,
+    SortLines,
+    DedupLines,
+    UrlEncode,
+    UrlDecode,
+    Base64Encode,
+    Base64Decode,
+    HtmlEncode,
+    HtmlDecode,
+    JsonPretty,
+    JsonMinify,
+    TomlToJson,
+    JsonToToml,
+    Escape,
+    Unescape,
+    Count,
+    Hash,
+}
+
+const ALL_TRANSFORMS: &'static [Transform] = &[
+    Transform::UpperCase, Transform::LowerCase, Transform::TitleCase, Transform::Trim,
+    Transform::SortLines, Transform::DedupLines,
+    Transform::UrlEncode, Transform::UrlDecode, Transform::Base64Encode, Transform::Base64Decode,
+    Transform::HtmlEncode, Transform::HtmlDecode,
+    Transform::JsonPretty, Transform::JsonMinify, Transform::TomlToJson, Transform::JsonToToml,
+    Transform::Escape, Transform::Unescape,
+    Transform::Count, Transform::Hash,
+];
+
+/// Only offered for quick-sent text, to keep them out of the top-level list
+pub struct TextTransformAction {
+    transform: Transform,
+}
+
+fn title_case(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut word_start = true;
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            word_start = true;
+            ret.push(ch);
+        } else if word_start {
+            word_start = false;
+            ret.extend(ch.to_uppercase());
+        } else {
+            ret.extend(ch.to_lowercase());
+        }
+    }
+    ret
+}
+
+/// Remove trailing whitespaces of each line, and leading and trailing blank lines
+fn trim(text: &str) -> String {
+    let lines : Vec<&str> = text.lines().map(|x| x.trim_right()).collect();
+    lines.join("\n").trim_matches('\n').into()
+}
+
+/// Remove duplicated lines, keeping the first occurrence
+fn dedup_lines(text: &str) -> String {
+    let mut seen = HashSet::new();
+    let lines : Vec<&str> = text.lines().filter(|x| seen.insert(*x)).collect();
+    lines.join("\n")
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut ret = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (idx, x)| n | (*x as u32) << (16 - idx * 8));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                ret.push(BASE64_CHARS[(n >> (18 - idx * 6)) as usize & 0x3f] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+/// Decode base64, also URL-safe variant, ignoring whitespaces and missing padding
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut ret = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+    for ch in text.chars().filter(|x| !x.is_whitespace()).take_while(|x| *x != '=') {
+        let value = match ch {
+            'A' ... 'Z' => ch as u32 - 'A' as u32,
+            'a' ... 'z' => ch as u32 - 'a' as u32 + 26,
+            '0' ... '9' => ch as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            _ => return Err(format!("Invalid base64 character {:?}", ch)),
+        };
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            ret.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Ok(ret)
+}
+
+fn json_to_toml(text: &str) -> Result<String, String> {
+    let value = serde_json::from_str::<serde_json::Value>(text).map_err(|e| e.to_string())?;
+    if !value.is_object() {
+        return Err("TOML document must be an object".into());
+    }
+    // convert to toml::Value first, which puts tables after other values
+    let value = toml::Value::try_from(value).map_err(|e| e.to_string())?;
+    toml::to_string(&value).map_err(|e| e.to_string())
+}
+
+fn digest<D: Digest>(mut hasher: D, text: &str) -> String {
+    hasher.input(text.as_bytes());
+    hasher.result_str()
+}
+
+/// Results of (text, badge)
+fn transform(kind: Transform, text: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let result = match kind {
+        Transform::UpperCase => text.to_uppercase(),
+        Transform::LowerCase => text.to_lowercase(),
+        Transform::TitleCase => title_case(text),
+        Transform::Trim => trim(text),
+        Transform::SortLines => {
+            let mut lines : Vec<&str> = text.lines().collect();
+            lines.sort();
+            lines.join("\n")
+        },
+        Transform::DedupLines => dedup_lines(text),
+        Transform::UrlEncode => form_urlencoded::byte_serialize(text.as_bytes()).collect(),
+        Transform::UrlDecode => percent_decode(text.replace('+', " ").as_bytes())
+            .decode_utf8().map_err(|e| e.to_string())?.into_owned(),
+        Transform::Base64Encode => base64_encode(text.as_bytes()),
+        Transform::Base64Decode => String::from_utf8(base64_decode(text)?)
+            .map_err(|_| "Decoded data is not UTF-8 text".to_string())?,
+        Transform::HtmlEncode => htmlescape::encode_minimal(text),
+        Transform::HtmlDecode => htmlescape::decode_html(text)
+            .map_err(|e| format!("Invalid HTML entity: {:?}", e))?,
+        Transform::JsonPretty | Transform::JsonMinify => {
+            let value = serde_json::from_str::<serde_json::Value>(text).map_err(|e| e.to_string())?;
+            let result = if kind == Transform::JsonPretty {
+                serde_json::to_string_pretty(&value)
+            } else {
+                serde_json::to_string(&value)
+            };
+            result.map_err(|e| e.to_string())?
+        },
+        Transform::TomlToJson => {
+            let value = text.parse::<toml::Value>().map_err(|e| e.to_string())?;
+            serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?
+        },
+        Transform::JsonToToml => json_to_toml(text)?,
+        Transform::Escape => {
+            let quoted = serde_json::to_string(text).map_err(|e| e.to_string())?;
+            quoted[1..quoted.len() - 1].into()
+        },
+        Transform::Unescape => serde_json::from_str::<String>(&format!("\"{}\"", text))
+            .map_err(|e| e.to_string())?,
+        Transform::Count => {
+            return Ok(vec![
+                (text.split_whitespace().count().to_string(), Some("Words".into())),
+                (text.lines().count().to_string(), Some("Lines".into())),
+                (text.chars().count().to_string(), Some("Characters".into())),
+                (text.len().to_string(), Some("Bytes".into())),
+            ]);
+        },
+        Transform::Hash => {
+            return Ok(vec![
+                (digest(crypto::md5::Md5::new(), text), Some("MD5".into())),
+                (digest(crypto::sha1::Sha1::new(), text), Some("SHA-1".into())),
+                (digest(crypto::sha2::Sha256::new(), text), Some("SHA-256".into())),
+                (digest(crypto::sha2::Sha512::new(), text), Some("SHA-512".into())),
+            ]);
+        },
+    };
+    Ok(vec![(result, None)])
+}
+
+impl TextTransformAction {
+    pub fn get_all() -> Vec<TextTransformAction> {
+        ALL_TRANSFORMS.iter().map(|x| TextTransformAction { transform: *x }).collect()
+    }
+}
+
+impl Action for TextTransformAction {
+    fn get_item(&self) -> Item {
+        let (title, ch) = match self.transform {
+            Transform::UpperCase => ("Upper Case", '\u{f031}'),
+            Transform::LowerCase => ("Lower Case", '\u{f031}'),
+            Transform::TitleCase => ("Title Case", '\u{f031}'),
+            Transform::Trim => ("Trim Whitespaces", '\u{f036}'),
+            Transform::SortLines => ("Sort Lines", '\u{f0cb}'),
+            Transform::DedupLines => ("Remove Duplicated Lines", '\u{f0cb}'),
+            Transform::UrlEncode => ("URL Encode", '\u{f0c1}'),
+            Transform::UrlDecode => ("URL Decode", '\u{f0c1}'),
+            Transform::Base64Encode => ("Base64 Encode", '\u{f121}'),
+            Transform::Base64Decode => ("Base64 Decode", '\u{f121}'),
+            Transform::HtmlEncode => ("HTML Encode", '\u{f121}'),
+            Transform::HtmlDecode => ("HTML Decode", '\u{f121}'),
+            Transform::JsonPretty => ("Pretty-print JSON", '\u{f1c9}'),
+            Transform::JsonMinify => ("Minify JSON", '\u{f1c9}'),
+            Transform::TomlToJson => ("Convert TOML to JSON", '\u{f1c9}'),
+            Transform::JsonToToml => ("Convert JSON to TOML", '\u{f1c9}'),
+            Transform::Escape => ("Escape", '\u{f10d}'),
+            Transform::Unescape => ("Unescape", '\u{f10d}'),
+            Transform::Count => ("Count Words, Lines and Characters", '\u{f1ec}'),
+            Transform::Hash => ("MD5 and SHA Hashes", '\u{f292}'),
+        };
+        let mut item = Item::new(title);
+        item.subtitle = match self.transform {
+            Transform::UrlEncode | Transform::UrlDecode => Some("Percent-encoding, as in query string".into()),
+            Transform::Escape | Transform::Unescape => Some("Backslash escapes, as in JSON string".into()),
+            _ => None,
+        };
+        item.badge = Some("Text".into());
+        item.priority = 20;
+        item.icon = Some(Icon::Character{ch: ch, font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_arg(&self, arg: &ActionArg) -> bool {
+        match *arg {
+            ActionArg::Text(_) => true,
+            _ => false,
+        }
+    }
+
+    fn content_support(&self, kind: ContentKind) -> ContentSupport {
+        match (kind, self.transform) {
+            (ContentKind::Json, Transform::JsonPretty) |
+            (ContentKind::Json, Transform::JsonMinify) |
+            (ContentKind::Json, Transform::JsonToToml) |
+            (ContentKind::Url, Transform::UrlDecode) => ContentSupport::Supported,
+            _ => ContentSupport::Unknown,
+        }
+    }
+
+    fn run_text(&self, text: &str) -> ActionResult {
+        let results = match transform(self.transform, text) {
+            Ok(results) => results,
+            Err(error) => return Err(Box::new(ActionError::new(&error))),
+        };
+        Ok(results.into_iter().map(|(result, badge)| {
+            let mut item = Item::new_text_item(&result);
+            item.badge = badge;
+            item.subtitle = Some(format!("{}, copy with Ctrl-C", self.get_item().title));
+            item
+        }).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actions::text_transform::{Transform, transform, title_case, trim, dedup_lines,
+                                  base64_encode, base64_decode};
+
+    fn run(t: Transform, text: &str) -> String {
+        transform(t, text).unwrap().remove(0).0
+    }
+
+    #[test]
+    fn text_test() {
+        assert_eq!(title_case("hello wORLD\tfoo"), "Hello World\tFoo");
+        assert_eq!(trim("\n  a  \nb \n\n"), "  a\nb");
+        assert_eq!(dedup_lines("b\na\nb\nc\na"), "b\na\nc");
+        assert_eq!(run(Transform::SortLines, "b\nc\na"), "a\nb\nc");
+    }
+
+    #[test]
+    fn base64_test() {
+        for &(plain, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"),
+                                  ("foobar", "Zm9vYmFy")].iter() {
+            assert_eq!(base64_encode(plain.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(base64_decode("Zm9v\nYmE").unwrap(), b"fooba");
+        assert!(base64_decode("Zm9v!").is_err());
+    }
+
+    #[test]
+    fn encoding_test() {
+        assert_eq!(run(Transform::UrlEncode, "a b&c=d/é"), "a+b%26c%3Dd%2F%C3%A9");
+        assert_eq!(run(Transform::UrlDecode, "a+b%26c%3Dd%2F%C3%A9"), "a b&c=d/é");
+        assert_eq!(run(Transform::Escape, "a\"b\n"), "a\\\"b\\n");
+        assert_eq!(run(Transform::Unescape, "a\\\"b\\n"), "a\"b\n");
+        assert_eq!(run(Transform::JsonMinify, "{ \"a\": [1, 2] }"), "{\"a\":[1,2]}");
+        assert_eq!(run(Transform::JsonToToml, "{\"a\": 1}").trim(), "a = 1");
+        assert!(run(Transform::TomlToJson, "a = 1").contains("\"a\": 1"));
+        assert!(transform(Transform::JsonToToml, "[1]").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Browse freedesktop trash, restore or delete trashed files
+
+use std::fs;
+use std::sync::Arc;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use mcore::item::{Item, Icon};
+use mcore::action::{Action, ActionResult};
+use actions::ActionError;
+use actions::file_browser::FileBrowserEntry;
+use actions::utils::trash::{self, TrashedItem};
+
+pub struct TrashAction {}
+
+/// A trashed file, run to get its operations
+struct TrashedItemAction {
+    item: TrashedItem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RestoreMode {
+    /// Restore to original path
+    Original,
+    /// Original path exists, restore with another name
+    Rename,
+    /// Original path exists, move it to trash first
+    Replace,
+}
+
+struct RestoreAction {
+    item: TrashedItem,
+    mode: RestoreMode,
+}
+
+#[derive(Clone)]
+enum Operation {
+    Delete(TrashedItem),
+    Empty,
+}
+
+/// Delete permanently, require confirmation
+struct DeleteAction {
+    op: Operation,
+    confirmed: bool,
+}
+
+fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+fn all_items() -> Vec<TrashedItem> {
+    let mut ret : Vec<TrashedItem> = trash::all_trashes().iter()
+        .flat_map(|x| trash::list(x))
+        .collect();
+    // newest first, unknown dates last
+    ret.sort_by(|a, b| b.deletion_date.cmp(&a.deletion_date));
+    ret
+}
+
+/// Available path for restoring `original` when it already exists
+fn renamed_path(original: &Path) -> PathBuf {
+    let dir = original.parent().unwrap_or(Path::new("/"));
+    let stem = match original.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => file_name(original),
+    };
+    let ext = match original.extension() {
+        Some(ext) => format!(".{}", ext.to_string_lossy()),
+        None => String::new(),
+    };
+    let mut idx = 1;
+    loop {
+        let name = if idx == 1 {
+            format!("{} (restored){}", stem, ext)
+        } else {
+            format!("{} (restored {}){}", stem, idx, ext)
+        };
+        let ret = dir.join(name);
+        if fs::symlink_metadata(&ret).is_err() {
+            return ret;
+        }
+        idx += 1;
+    }
+}
+
+fn path_item(path: &Path) -> Result<Item, Box<Error + Send + Sync>> {
+    match FileBrowserEntry::new(file_name(path), path.to_path_buf()) {
+        Some(entry) => Ok(Item::new_action_item(Arc::new(Box::new(entry)))),
+        None => Err(Box::new(ActionError::new(&format!("Invalid path: {:?}", path)))),
+    }
+}
+
+impl Action for TrashAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new("Trash");
+        item.subtitle = Some("Browse, restore or delete trashed files".into());
+        item.icon = Some(Icon::Character{ch: '\u{f1f8}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let items = all_items();
+        if items.len() == 0 {
+            return Err(Box::new(ActionError::new("Trash is empty")));
+        }
+        let mut ret = Vec::new();
+        let mut empty = Item::new_action_item(Arc::new(Box::new(DeleteAction {
+            op: Operation::Empty,
+            confirmed: false,
+        })));
+        empty.subtitle = Some(format!("{} items", items.len()));
+        empty.priority = -100;
+        ret.push(empty);
+        for (idx, x) in items.into_iter().enumerate() {
+            let mut item = Item::new_action_item(Arc::new(Box::new(TrashedItemAction { item: x })));
+            item.priority = idx as i32;
+            ret.push(item);
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for TrashedItemAction {
+    fn get_item(&self) -> Item {
+        let mut item = Item::new(&file_name(&self.item.original));
+        let from = self.item.original.parent().unwrap_or(Path::new("/")).to_string_lossy().into_owned();
+        item.subtitle = Some(match self.item.deletion_date {
+            Some(ref date) => format!("Deleted {} from {}", date.format("%Y-%m-%d %H:%M"), from),
+            None => format!("Deleted from {}", from),
+        });
+        item.badge = Some("Trash".into());
+        let is_dir = fs::symlink_metadata(self.item.files_path()).map(|x| x.is_dir()).unwrap_or(false);
+        item.icon = Some(Icon::Character{ch: if is_dir { '\u{f115}' } else { '\u{f016}' },
+                                         font: "FontAwesome".into()});
+        item.search_str = Some(self.item.original.to_string_lossy().into());
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let mut ret = Vec::new();
+        let modes = if fs::symlink_metadata(&self.item.original).is_ok() {
+            vec![RestoreMode::Rename, RestoreMode::Replace]
+        } else {
+            vec![RestoreMode::Original]
+        };
+        for mode in modes {
+            ret.push(Item::new_action_item(Arc::new(Box::new(RestoreAction {
+                item: self.item.clone(),
+                mode: mode,
+            }))));
+        }
+        if let Ok(mut item) = path_item(&self.item.files_path()) {
+            item.title = format!("Show {}", file_name(&self.item.original));
+            ret.push(item);
+        }
+        ret.push(Item::new_action_item(Arc::new(Box::new(DeleteAction {
+            op: Operation::Delete(self.item.clone()),
+            confirmed: false,
+        }))));
+        for (idx, item) in ret.iter_mut().enumerate() {
+            item.priority = idx as i32;
+        }
+        Ok(ret)
+    }
+}
+
+impl Action for RestoreAction {
+    fn get_item(&self) -> Item {
+        let name = file_name(&self.item.original);
+        let mut item = match self.mode {
+            RestoreMode::Original => {
+                let mut item = Item::new(&format!("Restore {}", name));
+                item.subtitle = Some(self.item.original.to_string_lossy().into());
+                item
+            },
+            RestoreMode::Rename => {
+                let target = renamed_path(&self.item.original);
+                let mut item = Item::new(&format!("Restore as {}", file_name(&target)));
+                item.subtitle = Some(format!("{:?} already exists", self.item.original));
+                item
+            },
+            RestoreMode::Replace => {
+                let mut item = Item::new(&format!("Restore and Replace {}", name));
+                item.subtitle = Some("Existing file would be moved to trash".into());
+                item
+            },
+        };
+        item.icon = Some(Icon::Character{ch: '\u{f0e2}', font: "FontAwesome".into()});
+        item
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn run(&self) -> ActionResult {
+        let target = match self.mode {
+            RestoreMode::Original => self.item.original.clone(),
+            RestoreMode::Rename => renamed_path(&self.item.original),
+            RestoreMode::Replace => {
+                info!("Move {:?} to trash before restoring", self.item.original);
+                trash::move_to_trash(&self.item.original)?;
+                self.item.original.clone()
+            },
+        };
+        info!("Restore {:?} to {:?}", self.item.files_path(), target);
+        self.item.restore(&target)?;
+        Ok(vec![path_item(&target)?])
+    }
+}
+
+impl Action for DeleteAction {
+    fn get_item(&self) -> Item {
+        let title = match self.op {
+            Operation::Delete(ref item) => format!("Delete {} Permanently", file_name(&item.original)),
+            Operation::Empty => "Empty Trash".into(),
+        };
+        if self.confirmed {
+            let mut item = Item::new(&format!("Confirm: {}", title));
+            item.subtitle = Some("This cannot be undone".into());
+            item.icon = Some(Icon::Character{ch: '\u{f071}', font: "FontAwesome".into()});
+            item
+        } else {
+            let mut item = Item::new(&title);
+            if let Operation::Delete(ref x) = self.op {
+                item.subtitle = Some(x.original.to_string_lossy().into());
+            }
+            item.icon = Some(Icon::Character{ch: '\u{f1f8}', font: "FontAwesome".into()});
+            item
+        }
+    }
+
+    fn accept_nothing(&self) -> bool { true }
+
+    fn should_return_items(&self) -> bool { !self.confirmed }
+
+    fn run(&self) -> ActionResult {
+        if !self.confirmed {
+            return Ok(vec![Item::new_action_item(Arc::new(Box::new(DeleteAction {
+                op: self.op.clone(),
+                confirmed: true,
+            })))]);
+        }
+        match self.op {
+            Operation::Delete(ref item) => {
+                info!("Delete {:?} permanently", item.files_path());
+                item.delete()?;
+            },
+            Operation::Empty => {
+                for x in trash::all_trashes() {
+                    info!("Empty trash {:?}", x.root);
+                    trash::empty(&x)?;
+                }
+            },
+        }
+        Ok(Vec::new())
+    }
+}
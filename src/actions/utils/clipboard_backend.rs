@@ -0,0 +1,183 @@
+//! System clipboard access, through GTK, X11 or Wayland
+
+use toml;
+
+use std::env;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use actions::utils::{self, x11_clipboard, wayland_clipboard};
+#[cfg(feature="use-gtk")]
+use actions::utils::gtk_clipboard;
+
+/// Set by password managers (KeePassXC, KDE, ...) to mark secret content
+pub const PASSWORD_MANAGER_HINT: &'static str = "x-kde-passwordManagerHint";
+
+/// Targets offered for text
+pub const TEXT_TARGETS: &'static [&'static str] = &[
+    "UTF8_STRING", "text/plain;charset=utf-8", "text/plain", "TEXT", "STRING", "COMPOUND_TEXT",
+];
+
+/// Contents of (target, data)
+pub type Contents = Vec<(String, Vec<u8>)>;
+
+/// Contents serving `text` in all text targets
+pub fn text_contents(text: &str) -> Contents {
+    TEXT_TARGETS.iter()
+        .map(|x| (x.to_string(), text.as_bytes().to_vec()))
+        .collect()
+}
+
+/// The one target to serve for backends unable to serve several:
+/// image if any, otherwise text, otherwise the first one
+pub fn pick_target(contents: Contents) -> Option<(String, Vec<u8>)> {
+    let idx = contents.iter().position(|x| x.0.starts_with("image/"))
+        .or(contents.iter().position(|x| TEXT_TARGETS.contains(&x.0.as_str())))
+        .unwrap_or(0);
+    contents.into_iter().nth(idx)
+}
+
+/// Whether the secret hint is offered
+pub fn has_secret_hint<B: ClipboardBackend + ?Sized>(backend: &B) -> bool {
+    backend.targets().iter().any(|x| x == PASSWORD_MANAGER_HINT) &&
+        backend.get_contents(PASSWORD_MANAGER_HINT).map(|x| x == b"secret").unwrap_or(false)
+}
+
+/// Backend of the CLIPBOARD selection
+pub trait ClipboardBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Text of CLIPBOARD, or PRIMARY if `primary`
+    fn get_text(&self, primary: bool) -> Option<String>;
+
+    /// Available targets (like MIME types)
+    fn targets(&self) -> Vec<String>;
+
+    /// Content of `target`, if available
+    fn get_contents(&self, target: &str) -> Option<Vec<u8>>;
+
+    /// Own the clipboard, serving `contents`
+    fn set_contents(&self, contents: Contents) -> Result<(), Box<Error + Send + Sync>>;
+
+    fn clear(&self);
+
+    /// Call `callback` whenever the clipboard owner changes, maybe in another thread
+    fn watch(&self, callback: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>>;
+
+    /// Set text, sensitive text is marked as secret so that clipboard managers would not record it
+    fn set_text(&self, text: &str, sensitive: bool) -> Result<(), Box<Error + Send + Sync>> {
+        let mut contents = text_contents(text);
+        if sensitive {
+            contents.push((PASSWORD_MANAGER_HINT.into(), b"secret".to_vec()));
+        }
+        self.set_contents(contents)
+    }
+
+    /// Whether current content is marked as secret
+    fn is_sensitive(&self) -> bool {
+        has_secret_hint(self)
+    }
+
+    /// Clear clipboard if it still holds `text`
+    fn clear_if(&self, text: &str) {
+        if self.get_text(false).map(|x| x == text).unwrap_or(false) {
+            self.clear();
+        }
+    }
+}
+
+lazy_static! {
+    /// None until configured or first used
+    static ref BACKEND: Mutex<Option<Option<Arc<Box<ClipboardBackend>>>>> = Mutex::new(None);
+}
+
+#[derive(Deserialize)]
+struct Config {
+    /// auto, gtk, x11, wayland or none
+    backend: String,
+}
+
+#[cfg(feature="use-gtk")]
+fn gtk_backend() -> Option<Box<ClipboardBackend>> {
+    gtk_clipboard::GtkBackend::new().map(|x| -> Box<ClipboardBackend> { Box::new(x) })
+}
+
+#[cfg(not(feature="use-gtk"))]
+fn gtk_backend() -> Option<Box<ClipboardBackend>> {
+    None
+}
+
+fn wayland_backend() -> Option<Box<ClipboardBackend>> {
+    if env::var("WAYLAND_DISPLAY").map(|x| x.len() > 0).unwrap_or(false) &&
+        utils::command_exists("wl-paste") && utils::command_exists("wl-copy") {
+        Some(Box::new(wayland_clipboard::WaylandBackend::new()))
+    } else {
+        None
+    }
+}
+
+fn x11_backend() -> Option<Box<ClipboardBackend>> {
+    if env::var("DISPLAY").map(|x| x.len() > 0).unwrap_or(false) && utils::command_exists("xclip") {
+        Some(Box::new(x11_clipboard::X11Backend::new(None)))
+    } else {
+        None
+    }
+}
+
+fn create(name: &str) -> Option<Arc<Box<ClipboardBackend>>> {
+    let backend = match name {
+        "auto" => gtk_backend().or_else(wayland_backend).or_else(x11_backend),
+        "gtk" => gtk_backend(),
+        "wayland" => wayland_backend(),
+        "x11" => x11_backend(),
+        "none" => None,
+        _ => {
+            warn!("Unknown clipboard backend: {}", name);
+            None
+        },
+    };
+    match backend {
+        Some(ref backend) => info!("Using {} clipboard backend", backend.name()),
+        None => warn!("No clipboard backend available for {}", name),
+    }
+    backend.map(Arc::new)
+}
+
+/// Load clipboard config, select backend
+pub fn configure(config: toml::Value) {
+    let name = match config.try_into::<Config>() {
+        Ok(config) => config.backend,
+        Err(error) => {
+            warn!("Error loading clipboard config: {}", error);
+            "auto".into()
+        }
+    };
+    *BACKEND.lock().unwrap() = Some(create(&name));
+}
+
+/// The clipboard backend, auto-selected if not configured
+pub fn get() -> Option<Arc<Box<ClipboardBackend>>> {
+    let mut backend = BACKEND.lock().unwrap();
+    if backend.is_none() {
+        *backend = Some(create("auto"));
+    }
+    backend.clone().unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actions::utils::clipboard_backend::pick_target;
+
+    #[test]
+    fn pick_target_test() {
+        let contents = vec![("text/html".to_string(), b"<b>a</b>".to_vec()),
+                            ("UTF8_STRING".to_string(), b"a".to_vec())];
+        assert_eq!(pick_target(contents.clone()).unwrap().0, "UTF8_STRING");
+        let mut with_image = contents.clone();
+        with_image.push(("image/png".into(), vec![0]));
+        assert_eq!(pick_target(with_image).unwrap().0, "image/png");
+        assert_eq!(pick_target(vec![contents[0].clone()]).unwrap().0, "text/html");
+        assert!(pick_target(Vec::new()).is_none());
+    }
+}
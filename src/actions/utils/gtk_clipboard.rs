@@ -0,0 +1,185 @@
+//! GTK clipboard, with functions not covered by gtk-rs called through gtk-sys
+
+extern crate gtk;
+extern crate gtk_sys;
+extern crate gdk_sys;
+extern crate glib_sys;
+extern crate glib;
+extern crate libc;
+
+extern crate gdk;
+
+use self::glib::signal::connect;
+use self::glib::translate::*;
+use self::gtk::Clipboard;
+use self::gtk_sys::{GtkClipboard, GtkSelectionData, GtkTargetEntry};
+use self::gdk_sys::GdkAtom;
+use self::libc::{c_char, c_int, c_uint, c_void};
+
+use std::ptr;
+use std::slice;
+use std::mem::transmute;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+
+use actions::utils::clipboard_backend::{ClipboardBackend, Contents};
+
+unsafe extern "C" fn get_func(_: *mut GtkClipboard, selection_data: *mut GtkSelectionData,
+                              info: c_uint, user_data: *mut c_void) {
+    let contents = &*(user_data as *const Contents);
+    if let Some(&(_, ref data)) = contents.get(info as usize) {
+        let target = gtk_sys::gtk_selection_data_get_target(selection_data);
+        gtk_sys::gtk_selection_data_set(selection_data, target, 8, data.as_ptr(), data.len() as c_int);
+    }
+}
+
+unsafe extern "C" fn clear_func(_: *mut GtkClipboard, user_data: *mut c_void) {
+    drop(Box::from_raw(user_data as *mut Contents));
+}
+
+fn intern(name: &str) -> GdkAtom {
+    let name = CString::new(name).unwrap();
+    unsafe { gdk_sys::gdk_atom_intern(name.as_ptr(), glib_sys::GFALSE) }
+}
+
+/// Own the clipboard, serving `contents` of (target, data)
+fn set_contents(clipboard: &gtk::Clipboard, contents: Contents) -> bool {
+    let names : Vec<CString> = contents.iter()
+        .map(|x| CString::new(x.0.as_str()).unwrap())
+        .collect();
+    let targets : Vec<GtkTargetEntry> = names.iter().enumerate()
+        .map(|(idx, name)| GtkTargetEntry { target: name.as_ptr() as *mut c_char, flags: 0, info: idx as c_uint })
+        .collect();
+    let user_data = Box::into_raw(Box::new(contents));
+    let ok = unsafe {
+        gtk_sys::gtk_clipboard_set_with_data(clipboard.to_glib_none().0, targets.as_ptr(), targets.len() as c_uint,
+                                             Some(get_func), Some(clear_func), user_data as *mut c_void)
+    };
+    if ok == 0 {
+        unsafe { drop(Box::from_raw(user_data)) };
+        warn!("Unable to set clipboard contents");
+    }
+    ok != 0
+}
+
+/// Contents of `target`, if available
+fn wait_for_contents(clipboard: &gtk::Clipboard, target: &str) -> Option<Vec<u8>> {
+    unsafe {
+        let selection_data = gtk_sys::gtk_clipboard_wait_for_contents(clipboard.to_glib_none().0, intern(target));
+        if selection_data == ptr::null_mut() {
+            return None;
+        }
+        let length = gtk_sys::gtk_selection_data_get_length(selection_data);
+        let data = gtk_sys::gtk_selection_data_get_data(selection_data);
+        let ret = if length >= 0 && data != ptr::null() {
+            Some(slice::from_raw_parts(data, length as usize).to_vec())
+        } else {
+            None
+        };
+        gtk_sys::gtk_selection_data_free(selection_data);
+        ret
+    }
+}
+
+/// Targets currently available
+fn wait_for_targets(clipboard: &gtk::Clipboard) -> Vec<String> {
+    let mut ret = Vec::new();
+    unsafe {
+        let mut atoms : *mut GdkAtom = ptr::null_mut();
+        let mut n_atoms : c_int = 0;
+        if gtk_sys::gtk_clipboard_wait_for_targets(clipboard.to_glib_none().0, &mut atoms, &mut n_atoms) == 0 {
+            return ret;
+        }
+        for atom in slice::from_raw_parts(atoms, n_atoms as usize) {
+            let name = gdk_sys::gdk_atom_name(*atom);
+            if name != ptr::null_mut() {
+                ret.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                glib_sys::g_free(name as *mut c_void);
+            }
+        }
+        glib_sys::g_free(atoms as *mut c_void);
+    }
+    ret
+}
+
+fn clear(clipboard: &gtk::Clipboard) {
+    unsafe { gtk_sys::gtk_clipboard_clear(clipboard.to_glib_none().0) };
+}
+
+unsafe extern "C" fn trampoline(clipboard: *mut GtkClipboard,
+                                _: *mut libc::c_void,
+                                f: &Box<Fn(&Clipboard) + 'static>) {
+    f(&Clipboard::from_glib_none(clipboard))
+}
+
+fn connect_clipboard_change<F>(clipboard: &Clipboard, f: F)
+where F: Fn(&Clipboard) + 'static {
+    unsafe {
+        let f: Box<Box<Fn(&Clipboard) + 'static>> =
+            Box::new(Box::new(f));
+        connect(clipboard.to_glib_none().0, "owner-change",
+                transmute(trampoline as usize), Box::into_raw(f) as *mut _);
+    }
+}
+
+fn get_clipboard(primary: bool) -> Clipboard {
+    gtk::Clipboard::get(&gdk::Atom::intern(if primary { "PRIMARY" } else { "CLIPBOARD" }))
+}
+
+/// Reading and watching must be done in main thread, while
+/// setting from other threads is deferred to main loop
+pub struct GtkBackend {}
+
+impl GtkBackend {
+    /// Only available when GTK is initialized
+    pub fn new() -> Option<GtkBackend> {
+        if gtk::is_initialized() { Some(GtkBackend {}) } else { None }
+    }
+}
+
+impl ClipboardBackend for GtkBackend {
+    fn name(&self) -> &'static str { "gtk" }
+
+    fn get_text(&self, primary: bool) -> Option<String> {
+        get_clipboard(primary).wait_for_text()
+    }
+
+    fn targets(&self) -> Vec<String> {
+        wait_for_targets(&get_clipboard(false))
+    }
+
+    fn get_contents(&self, target: &str) -> Option<Vec<u8>> {
+        wait_for_contents(&get_clipboard(false), target)
+    }
+
+    fn set_contents(&self, contents: Contents) -> Result<(), Box<Error + Send + Sync>> {
+        glib::idle_add(move || {
+            set_contents(&get_clipboard(false), contents.clone());
+            glib::Continue(false)
+        });
+        Ok(())
+    }
+
+    fn clear(&self) {
+        glib::idle_add(|| {
+            clear(&get_clipboard(false));
+            glib::Continue(false)
+        });
+    }
+
+    fn clear_if(&self, text: &str) {
+        let text = text.to_string();
+        glib::idle_add(move || {
+            let clipboard = get_clipboard(false);
+            if clipboard.wait_for_text().map(|x| x == text).unwrap_or(false) {
+                clear(&clipboard);
+            }
+            glib::Continue(false)
+        });
+    }
+
+    fn watch(&self, callback: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>> {
+        connect_clipboard_change(&get_clipboard(false), move |_| callback());
+        Ok(())
+    }
+}
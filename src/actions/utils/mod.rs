@@ -7,3 +7,72 @@
 
 pub mod open;
 pub mod subprocess;
+pub mod terminal;
+pub mod trash;
+pub mod mountinfo;
+pub mod paste;
+pub mod clipboard_backend;
+pub mod x11_clipboard;
+#[cfg(feature="x11-watch")]
+pub mod x11_watch;
+pub mod wayland_clipboard;
+pub mod thumbnail;
+#[cfg(feature="use-gtk")]
+pub mod gtk_clipboard;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Whether executable `name` can be found in PATH
+pub fn command_exists(name: &str) -> bool {
+    match Command::new("which").arg(name)
+                               .stdout(Stdio::null())
+                               .stderr(Stdio::null())
+                               .status() {
+        Ok(status) => status.success(),
+        Err(error) => {
+            warn!("Error running which {}: {}", name, error);
+            false
+        }
+    }
+}
+
+/// Directory for minions' own data (~/.minions), created if missing
+pub fn minions_dir() -> PathBuf {
+    let dir = env::home_dir().unwrap_or(PathBuf::from("/tmp")).join(".minions");
+    if !dir.is_dir() {
+        if let Err(error) = fs::create_dir_all(&dir) {
+            warn!("Unable to create {:?}: {}", dir, error);
+        }
+    }
+    dir
+}
+
+/// Expand leading `~` and environment variables like `$HOME` or `${HOME}` in path
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut ret = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        ret += &env::var("HOME").unwrap_or(String::new());
+        rest = &rest[1..];
+    }
+    while let Some(pos) = rest.find('$') {
+        ret += &rest[..pos];
+        rest = &rest[pos+1..];
+        let (name, remain) = if rest.starts_with('{') {
+            match rest.find('}') {
+                Some(end) => (&rest[1..end], &rest[end+1..]),
+                None => (&rest[1..], ""),
+            }
+        } else {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        ret += &env::var(name).unwrap_or(String::new());
+        rest = remain;
+    }
+    ret += rest;
+    PathBuf::from(ret)
+}
@@ -0,0 +1,92 @@
+//! Parse /proc/self/mountinfo
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// Root of the mount within the filesystem
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    pub options: String,
+    pub fs_type: String,
+    /// Mount source, like `/dev/sda1`
+    pub source: String,
+}
+
+/// Unescape octal sequences like `\040` (space)
+fn unescape(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'\\' && idx + 3 < bytes.len() &&
+           bytes[idx+1..idx+4].iter().all(|x| *x >= b'0' && *x <= b'7') {
+            let value = bytes[idx+1..idx+4].iter().fold(0u32, |acc, x| acc * 8 + (*x - b'0') as u32);
+            ret.push(value as u8);
+            idx += 4;
+        } else {
+            ret.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8_lossy(&ret).into_owned()
+}
+
+/// Parse one line, like:
+/// `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+pub fn parse_line(line: &str) -> Option<MountInfo> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let sep = match fields.iter().position(|x| *x == "-") {
+        Some(sep) => sep,
+        None => return None,
+    };
+    if sep < 6 || fields.len() < sep + 3 {
+        return None;
+    }
+    Some(MountInfo {
+        root: PathBuf::from(unescape(fields[3])),
+        mount_point: PathBuf::from(unescape(fields[4])),
+        options: fields[5].into(),
+        fs_type: fields[sep + 1].into(),
+        source: unescape(fields[sep + 2]),
+    })
+}
+
+/// All mounts of current process
+pub fn get_mounts() -> Vec<MountInfo> {
+    let mut content = String::new();
+    match File::open("/proc/self/mountinfo") {
+        Ok(mut file) => {
+            if let Err(error) = file.read_to_string(&mut content) {
+                warn!("Unable to read mountinfo: {}", error);
+            }
+        },
+        Err(error) => warn!("Unable to open mountinfo: {}", error),
+    }
+    content.lines().filter_map(parse_line).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use actions::utils::mountinfo::parse_line;
+
+    #[test]
+    fn parse_line_test() {
+        let info = parse_line("36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw,errors=continue").unwrap();
+        assert_eq!(info.root, Path::new("/mnt1"));
+        assert_eq!(info.mount_point, Path::new("/mnt/parent"));
+        assert_eq!(info.options, "rw,noatime");
+        assert_eq!(info.fs_type, "ext3");
+        assert_eq!(info.source, "/dev/root");
+
+        let info = parse_line("90 25 8:17 / /media/my\\040disk rw,nosuid - vfat /dev/sdb1 rw").unwrap();
+        assert_eq!(info.mount_point, Path::new("/media/my disk"));
+        assert_eq!(info.fs_type, "vfat");
+
+        assert!(parse_line("garbage").is_none());
+    }
+}
@@ -1,8 +1,191 @@
+//! Open files and URLs, according to opener rules in config,
+//! fallback to `xdg-open`
+
+extern crate url;
+extern crate glob;
+extern crate shlex;
+
+use self::url::Url;
+
+use toml;
+
 use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
+use actions::ActionError;
+use actions::linux_desktop_entry::LinuxDesktopEntry;
+use mcore::mime;
 use actions::utils::subprocess;
 
-pub fn that(path: &str) -> Result<(), Box<Error + Sync + Send>> {
-    let args: Vec<&str> = vec![path];
-    subprocess::spawn("xdg-open", &args)
+/// Rule mapping files or URLs to an opener.
+/// All conditions present must match; `mime` and `glob` apply to files,
+/// `scheme` and `host` apply to URLs
+#[derive(Deserialize, Debug)]
+struct Rule {
+    /// MIME type, like `image/png` or `image/*`
+    mime: Option<String>,
+    /// Glob pattern of file name, like `*.pdf`
+    glob: Option<String>,
+    /// URL scheme, like `https`
+    scheme: Option<String>,
+    /// Glob pattern of URL host, like `*.github.com`
+    host: Option<String>,
+
+    /// Open with desktop entry (file ID, like `firefox`)
+    desktop_entry: Option<String>,
+    /// Open with command template, `%s` is replaced by the file path or URL
+    command: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+lazy_static! {
+    static ref RULES: RwLock<Vec<Rule>> = RwLock::new(Vec::new());
+}
+
+/// Load opener rules
+pub fn configure(config: toml::Value) {
+    match config.try_into::<Config>() {
+        Ok(config) => {
+            debug!("Loaded {} opener rules", config.rules.len());
+            *RULES.write().unwrap() = config.rules;
+        },
+        Err(error) => {
+            warn!("Error loading opener config: {}", error);
+        }
+    }
+}
+
+enum Target {
+    File(PathBuf),
+    URL(Url),
+}
+
+fn parse_target(s: &str) -> Target {
+    if s.starts_with('/') {
+        return Target::File(PathBuf::from(s));
+    }
+    match Url::parse(s) {
+        Ok(url) => if url.scheme() == "file" {
+            match url.to_file_path() {
+                Ok(path) => Target::File(path),
+                Err(_) => Target::URL(url),
+            }
+        } else if url.scheme().len() > 1 { // single-letter "scheme" is not likely an URL
+            Target::URL(url)
+        } else {
+            Target::File(PathBuf::from(s))
+        },
+        Err(_) => Target::File(PathBuf::from(s)),
+    }
+}
+
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    match glob::Pattern::new(pattern) {
+        Ok(pattern) => pattern.matches(text),
+        Err(error) => {
+            warn!("Invalid glob pattern {}: {}", pattern, error);
+            false
+        }
+    }
+}
+
+impl Rule {
+    fn matches_file(&self, path: &Path, file_mime: &str) -> bool {
+        if self.scheme.is_some() || self.host.is_some() {
+            return false;
+        }
+        if let Some(ref pattern) = self.mime {
+            if !mime::matches(pattern, file_mime) {
+                return false;
+            }
+        }
+        if let Some(ref pattern) = self.glob {
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => return false,
+            };
+            if !glob_matches(pattern, &name) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn matches_url(&self, url: &Url) -> bool {
+        if self.mime.is_some() || self.glob.is_some() {
+            return false;
+        }
+        if let Some(ref scheme) = self.scheme {
+            if scheme != url.scheme() {
+                return false;
+            }
+        }
+        if let Some(ref pattern) = self.host {
+            match url.host_str() {
+                Some(host) => if !glob_matches(pattern, host) {
+                    return false;
+                },
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn open(&self, target: &str) -> Result<(), Box<Error + Sync + Send>> {
+        if let Some(ref id) = self.desktop_entry {
+            match LinuxDesktopEntry::find(id) {
+                Some(entry) => entry.launch(Some(target)),
+                None => Err(Box::new(ActionError::new(&format!("Desktop entry {} not found", id)))),
+            }
+        } else if let Some(ref command) = self.command {
+            let cmd = expand_command(command, target).ok_or(
+                ActionError::new(&format!("Invalid opener command: {}", command)))?;
+            let args : Vec<&str> = cmd[1..].iter().map(|x| x.as_str()).collect();
+            subprocess::spawn(&cmd[0], &args)
+        } else {
+            Err(Box::new(ActionError::new("Opener rule has neither desktop_entry nor command")))
+        }
+    }
+}
+
+/// Expand command template, `%s` is replaced by target, which is appended if `%s` is not present
+fn expand_command(template: &str, target: &str) -> Option<Vec<String>> {
+    let parts = match shlex::split(template) {
+        Some(ref parts) if parts.len() > 0 => parts.clone(),
+        _ => return None,
+    };
+    let has_placeholder = parts.iter().any(|x| x.contains("%s"));
+    let mut ret : Vec<String> = parts.into_iter().map(|x| x.replace("%s", target)).collect();
+    if !has_placeholder {
+        ret.push(target.into());
+    }
+    Some(ret)
+}
+
+/// Open file path or URL
+pub fn that(target: &str) -> Result<(), Box<Error + Sync + Send>> {
+    let rules = RULES.read().unwrap();
+    let rule = match parse_target(target) {
+        Target::File(ref path) => {
+            let file_mime = mime::guess(path);
+            debug!("MIME type of {:?}: {}", path, file_mime);
+            rules.iter().find(|rule| rule.matches_file(path, &file_mime))
+        },
+        Target::URL(ref url) => {
+            rules.iter().find(|rule| rule.matches_url(url))
+        },
+    };
+    if let Some(rule) = rule {
+        debug!("Opening {} with rule {:?}", target, rule);
+        return rule.open(target);
+    }
+
+    let args: Vec<&str> = vec![target];
+    subprocess::spawn_app("xdg-open", "xdg-open", &args)
 }
@@ -0,0 +1,183 @@
+//! Paste into the window focused before minions shows up, through xdotool, wtype or ydotool
+
+use toml;
+
+use std::env;
+use std::thread;
+use std::error::Error;
+use std::io::prelude::*;
+use std::time::Duration;
+use std::sync::{Mutex, RwLock};
+use std::process::{Command, Stdio};
+
+use actions::ActionError;
+use actions::utils::command_exists;
+
+/// How text is pasted
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PasteMethod {
+    /// Type the text as key strokes
+    #[serde(rename = "type")]
+    Type,
+    /// Set clipboard then send Ctrl+V
+    #[serde(rename = "ctrl-v")]
+    CtrlV,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    /// wtype or ydotool on Wayland, xdotool otherwise
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "xdotool")]
+    Xdotool,
+    #[serde(rename = "wtype")]
+    Wtype,
+    #[serde(rename = "ydotool")]
+    Ydotool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Config {
+    /// Whether Enter on text items pastes them
+    enabled: bool,
+    method: PasteMethod,
+    tool: Tool,
+    /// Milliseconds to wait for minions to hide
+    delay: u64,
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config {
+        enabled: true,
+        method: PasteMethod::Type,
+        tool: Tool::Auto,
+        delay: 150,
+    });
+    /// X11 window focused before minions shows up
+    static ref PREVIOUS_WINDOW: Mutex<Option<String>> = Mutex::new(None);
+    /// Running paste, so that the process would not exit before it
+    static ref PENDING: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+/// Load paste config
+pub fn configure(config: toml::Value) {
+    match config.try_into::<Config>() {
+        Ok(config) => {
+            debug!("Paste config: {:?}", config);
+            *CONFIG.write().unwrap() = config;
+        },
+        Err(error) => {
+            warn!("Error loading paste config: {}", error);
+        }
+    }
+}
+
+/// Paste method, None if disabled
+pub fn method() -> Option<PasteMethod> {
+    let config = CONFIG.read().unwrap();
+    if config.enabled { Some(config.method) } else { None }
+}
+
+fn is_wayland() -> bool {
+    env::var("WAYLAND_DISPLAY").map(|x| x.len() > 0).unwrap_or(false)
+}
+
+fn detect_tool(tool: Tool) -> Option<Tool> {
+    let candidates = match tool {
+        Tool::Auto if is_wayland() => vec![Tool::Wtype, Tool::Ydotool],
+        Tool::Auto => vec![Tool::Xdotool],
+        tool @ _ => vec![tool],
+    };
+    candidates.into_iter().find(|x| command_exists(match *x {
+        Tool::Auto | Tool::Xdotool => "xdotool",
+        Tool::Wtype => "wtype",
+        Tool::Ydotool => "ydotool",
+    }))
+}
+
+/// Command typing text from stdin, or sending Ctrl+V
+fn paste_command(tool: Tool, typing: bool) -> Command {
+    let (program, args) = match (tool, typing) {
+        (Tool::Wtype, true) => ("wtype", vec!["-"]),
+        (Tool::Wtype, false) => ("wtype", vec!["-M", "ctrl", "v", "-m", "ctrl"]),
+        (Tool::Ydotool, true) => ("ydotool", vec!["type", "--file", "-"]),
+        // KEY_LEFTCTRL is 29, KEY_V is 47
+        (Tool::Ydotool, false) => ("ydotool", vec!["key", "29:1", "47:1", "47:0", "29:0"]),
+        (_, true) => ("xdotool", vec!["type", "--clearmodifiers", "--delay", "0", "--file", "-"]),
+        (_, false) => ("xdotool", vec!["key", "--clearmodifiers", "ctrl+v"]),
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    cmd
+}
+
+/// Run `cmd` with `input` as stdin, the text is never passed by arguments
+fn run_with_input(cmd: &mut Command, input: &[u8]) -> Result<(), Box<Error + Send + Sync>> {
+    debug!("Executing: {:?}", cmd);
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+                       .spawn()?;
+    if let Some(ref mut stdin) = child.stdin {
+        stdin.write_all(input)?;
+    }
+    drop(child.stdin.take());
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Box::new(ActionError::new(&format!("{:?} exited with {}", cmd, status))));
+    }
+    Ok(())
+}
+
+/// Remember the focused window, should be called before minions shows up.
+/// Only possible on X11, Wayland compositors restore focus by themselves
+pub fn remember_window() {
+    let mut previous = PREVIOUS_WINDOW.lock().unwrap();
+    *previous = None;
+    if method().is_none() || is_wayland() {
+        return;
+    }
+    match Command::new("xdotool").arg("getactivewindow").stderr(Stdio::null()).output() {
+        Ok(ref output) if output.status.success() => {
+            let window = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            debug!("Previous window: {}", window);
+            if window.len() > 0 {
+                *previous = Some(window);
+            }
+        },
+        Ok(_) => warn!("Unable to get active window"),
+        Err(error) => warn!("Unable to run xdotool: {}", error),
+    }
+}
+
+/// Paste into the previous window in background, after minions hides.
+/// Type `text` if given, otherwise send Ctrl+V for content already in clipboard
+pub fn paste(text: Option<String>) -> Result<(), Box<Error + Send + Sync>> {
+    let config = CONFIG.read().unwrap().clone();
+    let tool = match detect_tool(config.tool) {
+        Some(tool) => tool,
+        None => return Err(Box::new(ActionError::new("No paste tool available, install xdotool, wtype or ydotool"))),
+    };
+    let window = PREVIOUS_WINDOW.lock().unwrap().clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(config.delay));
+        if let Some(window) = window {
+            if let Err(error) = run_with_input(Command::new("xdotool").args(&["windowactivate", "--sync", &window]), b"") {
+                warn!("Unable to activate previous window: {}", error);
+            }
+        }
+        let input = text.clone().unwrap_or(String::new());
+        if let Err(error) = run_with_input(&mut paste_command(tool, text.is_some()), input.as_bytes()) {
+            warn!("Unable to paste: {}", error);
+        }
+    });
+    *PENDING.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+/// Wait for running paste, before exiting
+pub fn wait() {
+    let pending = PENDING.lock().unwrap().take();
+    if let Some(handle) = pending {
+        let _ = handle.join();
+    }
+}
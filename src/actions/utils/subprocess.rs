@@ -6,13 +6,161 @@
 */
 
 extern crate nix;
+extern crate uuid;
+
+use self::uuid::Uuid;
+
+use toml;
 
 use std;
+use std::env;
 use std::error::Error;
 use std::ffi::CString;
+use std::path::Path;
+use std::process::Command;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use actions::utils::command_exists;
+
+/// How applications are launched
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LaunchStrategy {
+    /// Double-fork, the child is re-parented to init
+    #[serde(rename = "fork")]
+    Fork,
+    /// Run each application in its own transient systemd user scope,
+    /// so that it gets its own cgroup instead of sharing minions'
+    #[serde(rename = "systemd-scope")]
+    SystemdScope,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    strategy: LaunchStrategy,
+}
+
+/// systemd-run exiting with failure within this time is taken as failing to start the scope
+const SCOPE_START_TIMEOUT: u64 = 1000;
+
+lazy_static! {
+    static ref STRATEGY: RwLock<LaunchStrategy> = RwLock::new(LaunchStrategy::Fork);
+    static ref SYSTEMD_AVAILABLE: bool = systemd_user_available();
+}
+
+/// Load launcher config, should be called once before spawning anything
+pub fn configure(config: toml::Value) {
+    match config.try_into::<Config>() {
+        Ok(config) => {
+            debug!("Launch strategy: {:?}", config.strategy);
+            *STRATEGY.write().unwrap() = config.strategy;
+        },
+        Err(error) => {
+            warn!("Error loading launcher config: {}", error);
+        }
+    }
+}
+
+fn systemd_user_available() -> bool {
+    let runtime_dir = match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => dir,
+        Err(_) => {
+            debug!("XDG_RUNTIME_DIR not set, systemd user instance unavailable");
+            return false;
+        }
+    };
+    if !Path::new(&runtime_dir).join("systemd/private").exists() {
+        debug!("systemd user instance not running");
+        return false;
+    }
+    command_exists("systemd-run")
+}
+
+/// Escape string for use in systemd unit name, like `systemd-escape`
+fn escape_unit_name(s: &str) -> String {
+    let mut ret = String::new();
+    for (idx, b) in s.bytes().enumerate() {
+        let c = b as char;
+        if (b < 0x80 && c.is_alphanumeric()) || c == '_' || c == ':' || (c == '.' && idx > 0) {
+            ret.push(c);
+        } else {
+            ret += &format!("\\x{:02x}", b);
+        }
+    }
+    ret
+}
 
+fn scope_unit_name(app_id: &str) -> String {
+    format!("app-minions-{}-{}.scope",
+            escape_unit_name(app_id),
+            Uuid::new_v4().simple().to_string())
+}
 
+/// Spawn command in background, using the basename of command as application ID
 pub fn spawn(cmd: &str, args: &[&str]) -> Result<(), Box<Error + Sync + Send>> {
+    let app_id = match Path::new(cmd).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => cmd.to_string(),
+    };
+    spawn_app(&app_id, cmd, args)
+}
+
+/// Spawn command in background for application `app_id`,
+/// according to the configured launch strategy
+pub fn spawn_app(app_id: &str, cmd: &str, args: &[&str]) -> Result<(), Box<Error + Sync + Send>> {
+    let strategy = *STRATEGY.read().unwrap();
+    if strategy == LaunchStrategy::SystemdScope {
+        if *SYSTEMD_AVAILABLE {
+            return spawn_scope(app_id, cmd, args);
+        } else {
+            debug!("systemd not available, fallback to fork");
+        }
+    }
+    fork_exec(cmd, args)
+}
+
+/// Run command in a new systemd scope, fallback to fork if systemd-run fails.
+/// systemd-run keeps running as the command once the scope is started,
+/// so it's waited in background, and only an early failure is taken as its own
+fn spawn_scope(app_id: &str, cmd: &str, args: &[&str]) -> Result<(), Box<Error + Sync + Send>> {
+    let unit = format!("--unit={}", scope_unit_name(app_id));
+    debug!("Launching {} in systemd scope: {}", app_id, unit);
+    let started = Instant::now();
+    let child = Command::new("systemd-run")
+        .arg("--user").arg("--scope").arg("--quiet").arg(&unit).arg("--")
+        .arg(cmd).args(args)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            warn!("Unable to run systemd-run: {}, fallback to fork", error);
+            return fork_exec(cmd, args);
+        },
+    };
+    let cmd = cmd.to_string();
+    let args : Vec<String> = args.iter().map(|x| x.to_string()).collect();
+    thread::spawn(move || {
+        let status = match child.wait() {
+            Ok(status) => status,
+            Err(error) => {
+                warn!("Error waiting for systemd-run: {}", error);
+                return;
+            },
+        };
+        if status.success() || started.elapsed() >= Duration::from_millis(SCOPE_START_TIMEOUT) {
+            return;
+        }
+        warn!("systemd-run failed with {}, fallback to fork", status);
+        let args : Vec<&str> = args.iter().map(|x| x.as_str()).collect();
+        if let Err(error) = fork_exec(&cmd, &args) {
+            warn!("Unable to launch {}: {}", cmd, error);
+        }
+    });
+    Ok(())
+}
+
+fn fork_exec(cmd: &str, args: &[&str]) -> Result<(), Box<Error + Sync + Send>> {
 
     let execv_filename = CString::new(cmd)?;
     let mut execv_args = vec![execv_filename.clone()];
@@ -0,0 +1,162 @@
+//! Run commands in terminal emulator
+
+extern crate shlex;
+
+use toml;
+
+use std::env;
+use std::error::Error;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use actions::ActionError;
+use actions::utils::command_exists;
+
+/// Built-in command templates for common terminal emulators,
+/// `%c` is replaced by the command and its arguments
+static BUILTIN_TEMPLATES: &'static [(&'static str, &'static str)] = &[
+    ("xdg-terminal-exec", "xdg-terminal-exec %c"),
+    ("x-terminal-emulator", "x-terminal-emulator -e %c"),
+    ("gnome-terminal", "gnome-terminal -- %c"),
+    ("kitty", "kitty %c"),
+    ("alacritty", "alacritty -e %c"),
+    ("foot", "foot %c"),
+    ("wezterm", "wezterm start -- %c"),
+    ("konsole", "konsole -e %c"),
+    ("xfce4-terminal", "xfce4-terminal -x %c"),
+    ("mate-terminal", "mate-terminal -x %c"),
+    ("terminator", "terminator -x %c"),
+    ("roxterm", "roxterm -x %c"),
+    ("urxvt", "urxvt -e %c"),
+    ("rxvt", "rxvt -e %c"),
+    ("st", "st -e %c"),
+    ("termit", "termit -e %c"),
+    ("Eterm", "Eterm -e %c"),
+    ("aterm", "aterm -e %c"),
+    ("uxterm", "uxterm -e %c"),
+    ("xterm", "xterm -e %c"),
+];
+
+#[derive(Deserialize)]
+struct Config {
+    emulator: Option<String>,
+    templates: Option<HashMap<String, String>>,
+}
+
+struct TerminalConfig {
+    emulator: Option<String>,
+    templates: HashMap<String, String>,
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<TerminalConfig> = RwLock::new(TerminalConfig {
+        emulator: None,
+        templates: HashMap::new(),
+    });
+    static ref DETECTED_EMULATOR: Option<String> = detect_emulator();
+}
+
+/// Load terminal config
+pub fn configure(config: toml::Value) {
+    match config.try_into::<Config>() {
+        Ok(config) => {
+            let mut current = CONFIG.write().unwrap();
+            current.emulator = match config.emulator {
+                Some(ref emulator) if emulator.len() > 0 => Some(emulator.clone()),
+                _ => None,
+            };
+            current.templates = config.templates.unwrap_or(HashMap::new());
+        },
+        Err(error) => {
+            warn!("Error loading terminal config: {}", error);
+        }
+    }
+}
+
+fn detect_emulator() -> Option<String> {
+    let mut candidates = Vec::new();
+    if let Ok(terminal) = env::var("TERMINAL") {
+        if terminal.len() > 0 {
+            candidates.push(terminal);
+        }
+    }
+    for &(name, _) in BUILTIN_TEMPLATES {
+        candidates.push(name.into());
+    }
+    let ret = candidates.into_iter().find(|x| command_exists(x));
+    debug!("Detected terminal emulator: {:?}", ret);
+    ret
+}
+
+/// Expand template for command, `%c` in template is replaced by `cmd`;
+/// `cmd` is appended if `%c` is not present
+fn expand_template(template: &str, cmd: &[String]) -> Option<Vec<String>> {
+    let parts = match shlex::split(template) {
+        Some(ref parts) if parts.len() > 0 => parts.clone(),
+        _ => return None,
+    };
+    let mut ret = Vec::new();
+    let mut expanded = false;
+    for part in parts.into_iter() {
+        if part == "%c" {
+            ret.extend(cmd.iter().cloned());
+            expanded = true;
+        } else {
+            ret.push(part);
+        }
+    }
+    if !expanded {
+        ret.extend(cmd.iter().cloned());
+    }
+    Some(ret)
+}
+
+fn get_template(emulator: &str, config: &TerminalConfig) -> String {
+    if let Some(template) = config.templates.get(emulator) {
+        return template.clone();
+    }
+    let basename = emulator.rsplit('/').next().unwrap_or(emulator);
+    for &(name, template) in BUILTIN_TEMPLATES {
+        if name == basename {
+            return template.replacen(name, emulator, 1);
+        }
+    }
+    // most emulators accept `-e`
+    format!("{} -e %c", emulator)
+}
+
+/// Get command line running `cmd` in terminal emulator
+pub fn wrap_command(cmd: &[String]) -> Result<Vec<String>, Box<Error + Send + Sync>> {
+    let config = CONFIG.read().unwrap();
+    let emulator = match config.emulator {
+        Some(ref emulator) => emulator.clone(),
+        None => match *DETECTED_EMULATOR {
+            Some(ref emulator) => emulator.clone(),
+            None => return Err(Box::new(ActionError::new("No terminal emulator found"))),
+        },
+    };
+    let template = get_template(&emulator, &config);
+    trace!("Terminal template for {}: {}", emulator, template);
+    match expand_template(&template, cmd) {
+        Some(ret) => Ok(ret),
+        None => Err(Box::new(ActionError::new(&format!("Invalid terminal template: {}", template)))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actions::utils::terminal::expand_template;
+
+    #[test]
+    fn expand_template_test() {
+        let cmd = vec!["vim".to_string(), "a file".to_string()];
+        assert_eq!(expand_template("gnome-terminal -- %c", &cmd).unwrap(),
+                   vec!["gnome-terminal", "--", "vim", "a file"]);
+        assert_eq!(expand_template("kitty", &cmd).unwrap(),
+                   vec!["kitty", "vim", "a file"]);
+        assert_eq!(expand_template("'my term' -e %c --hold", &cmd).unwrap(),
+                   vec!["my term", "-e", "vim", "a file", "--hold"]);
+        assert!(expand_template("", &cmd).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Small thumbnails of images, to be shown as icons
+
+#[cfg(feature="use-gtk")]
+extern crate gdk_pixbuf;
+#[cfg(feature="use-gtk")]
+extern crate gdk_pixbuf_sys;
+#[cfg(feature="use-gtk")]
+extern crate glib;
+
+use std::error::Error;
+use std::path::Path;
+
+use actions::ActionError;
+
+/// Longer side of thumbnails, in pixels
+#[cfg(feature="use-gtk")]
+const SIZE: i32 = 96;
+
+/// Decode image `data` and save it scaled down to `path` as PNG
+#[cfg(feature="use-gtk")]
+pub fn generate(data: &[u8], path: &Path) -> Result<(), Box<Error + Send + Sync>> {
+    use self::glib::translate::ToGlibPtr;
+    use std::cmp;
+    use std::ptr;
+    use std::ffi::CString;
+
+    let loader = gdk_pixbuf::PixbufLoader::new();
+    let loaded = loader.loader_write(data).and_then(|_| loader.close());
+    let image = match (loaded, loader.get_pixbuf()) {
+        (Ok(_), Some(image)) => image,
+        _ => return Err(Box::new(ActionError::new("Unable to decode image"))),
+    };
+    let (width, height) = (image.get_width(), image.get_height());
+    let scale = SIZE as f64 / cmp::max(width, height) as f64;
+    let image = if scale < 1.0 {
+        let scaled = image.scale_simple(cmp::max(1, (width as f64 * scale) as i32),
+                                        cmp::max(1, (height as f64 * scale) as i32),
+                                        gdk_pixbuf::InterpType::Bilinear);
+        match scaled {
+            Some(scaled) => scaled,
+            None => return Err(Box::new(ActionError::new("Unable to scale image"))),
+        }
+    } else {
+        image
+    };
+    let filename = CString::new(path.to_string_lossy().as_bytes())?;
+    let ok = unsafe {
+        gdk_pixbuf_sys::gdk_pixbuf_savev(image.to_glib_none().0, filename.as_ptr(), b"png\0".as_ptr() as *const _,
+                                         ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+    };
+    if ok == 0 {
+        return Err(Box::new(ActionError::new(&format!("Unable to save thumbnail {:?}", path))));
+    }
+    Ok(())
+}
+
+/// Images can only be decoded with gdk-pixbuf
+#[cfg(not(feature="use-gtk"))]
+pub fn generate(_: &[u8], _: &Path) -> Result<(), Box<Error + Send + Sync>> {
+    Err(Box::new(ActionError::new("Thumbnails are not supported without GTK")))
+}
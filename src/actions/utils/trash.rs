@@ -0,0 +1,331 @@
+//! Freedesktop trash, see https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+
+extern crate libc;
+extern crate url;
+extern crate chrono;
+
+use self::url::percent_encoding::percent_decode;
+use self::chrono::{Local, NaiveDateTime};
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::os::unix::fs::{MetadataExt, DirBuilderExt};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use actions::ActionError;
+use mcore::xdg;
+use actions::utils::mountinfo;
+
+/// A trash directory, containing `files` and `info`
+#[derive(Debug, Clone)]
+pub struct TrashDir {
+    pub root: PathBuf,
+    /// Top directory of the mounted volume, None for home trash.
+    /// Paths in trash info are relative to it
+    pub topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    pub fn files_dir(&self) -> PathBuf { self.root.join("files") }
+    pub fn info_dir(&self) -> PathBuf { self.root.join("info") }
+
+    fn ensure_dirs(&self) -> Result<(), Box<Error + Send + Sync>> {
+        for dir in vec![self.files_dir(), self.info_dir()] {
+            if !dir.is_dir() {
+                fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file or directory in trash
+#[derive(Debug, Clone)]
+pub struct TrashedItem {
+    pub trash: TrashDir,
+    /// File name in `files` directory
+    pub name: String,
+    /// Absolute original path
+    pub original: PathBuf,
+    pub deletion_date: Option<NaiveDateTime>,
+}
+
+impl TrashedItem {
+    pub fn files_path(&self) -> PathBuf { self.trash.files_dir().join(&self.name) }
+    pub fn info_path(&self) -> PathBuf { self.trash.info_dir().join(format!("{}.trashinfo", self.name)) }
+
+    /// Move back to `target`, which must not exist
+    pub fn restore(&self, target: &Path) -> Result<(), Box<Error + Send + Sync>> {
+        if fs::symlink_metadata(target).is_ok() {
+            return Err(Box::new(ActionError::new(&format!("File already exists: {:?}", target))));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.files_path(), target)?;
+        fs::remove_file(self.info_path())?;
+        Ok(())
+    }
+
+    /// Delete permanently
+    pub fn delete(&self) -> Result<(), Box<Error + Send + Sync>> {
+        let path = self.files_path();
+        // remove the file first, so that a failure would not leave an orphan info file
+        match fs::symlink_metadata(&path) {
+            Ok(ref meta) if meta.is_dir() => fs::remove_dir_all(&path)?,
+            Ok(_) => fs::remove_file(&path)?,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {},
+            Err(error) => return Err(Box::new(error)),
+        }
+        fs::remove_file(self.info_path())?;
+        Ok(())
+    }
+}
+
+/// Percent-encode `path` for the "Path=" key of trash info files,
+/// every byte except unreserved characters and '/' is escaped
+fn encode_path(path: &Path) -> String {
+    let mut ret = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A' ... b'Z' | b'a' ... b'z' | b'0' ... b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => ret.push(byte as char),
+            _ => ret.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    ret
+}
+
+/// Parse content of a .trashinfo file, return (original path, deletion date)
+pub fn parse_trashinfo(content: &str) -> Option<(PathBuf, Option<NaiveDateTime>)> {
+    let mut in_section = false;
+    let mut path = None;
+    let mut date = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        if line.starts_with("Path=") {
+            let decoded = percent_decode(line["Path=".len()..].as_bytes()).collect::<Vec<u8>>();
+            path = Some(PathBuf::from(OsStr::from_bytes(&decoded)));
+        } else if line.starts_with("DeletionDate=") {
+            date = NaiveDateTime::parse_from_str(&line["DeletionDate=".len()..], "%Y-%m-%dT%H:%M:%S").ok();
+        }
+    }
+    path.map(|x| (x, date))
+}
+
+/// All items in `trash`
+pub fn list(trash: &TrashDir) -> Vec<TrashedItem> {
+    let entries = match trash.info_dir().read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut ret = Vec::new();
+    for entry in entries.filter_map(|x| x.ok()) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.ends_with(".trashinfo") {
+            continue;
+        }
+        let mut content = String::new();
+        if let Err(error) = File::open(entry.path()).and_then(|mut f| f.read_to_string(&mut content)) {
+            warn!("Unable to read {:?}: {}", entry.path(), error);
+            continue;
+        }
+        let (path, date) = match parse_trashinfo(&content) {
+            Some(x) => x,
+            None => {
+                warn!("Invalid trash info file {:?}", entry.path());
+                continue;
+            }
+        };
+        let item = TrashedItem {
+            trash: trash.clone(),
+            name: file_name[..file_name.len() - ".trashinfo".len()].into(),
+            original: match trash.topdir {
+                Some(ref topdir) if path.is_relative() => topdir.join(path),
+                _ => path,
+            },
+            deletion_date: date,
+        };
+        if fs::symlink_metadata(item.files_path()).is_ok() {
+            ret.push(item);
+        }
+    }
+    ret
+}
+
+/// Home trash and trashes of all mounted volumes which exist
+pub fn all_trashes() -> Vec<TrashDir> {
+    let mut ret = vec![home_trash()];
+    for mount in mountinfo::get_mounts() {
+        for trash in volume_trashes(&mount.mount_point) {
+            if trash.root.is_dir() && !ret.iter().any(|x| x.root == trash.root) {
+                ret.push(trash);
+            }
+        }
+    }
+    ret.into_iter().filter(|x| x.root.is_dir()).collect()
+}
+
+/// Delete everything in `trash`
+pub fn empty(trash: &TrashDir) -> Result<(), Box<Error + Send + Sync>> {
+    for item in list(trash) {
+        item.delete()?;
+    }
+    // files without info
+    if let Ok(entries) = trash.files_dir().read_dir() {
+        for entry in entries.filter_map(|x| x.ok()) {
+            let path = entry.path();
+            if fs::symlink_metadata(&path)?.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn uid() -> u32 {
+    unsafe { libc::getuid() as u32 }
+}
+
+/// $XDG_DATA_HOME/Trash
+pub fn home_trash() -> TrashDir {
+    TrashDir {
+        root: xdg::data_home().join("Trash"),
+        topdir: None,
+    }
+}
+
+/// Trash directories of volume mounted at `topdir` (which may not exist)
+pub fn volume_trashes(topdir: &Path) -> Vec<TrashDir> {
+    let uid = uid();
+    let mut ret = Vec::new();
+    // $topdir/.Trash/$uid, only if .Trash is a sticky directory and not a symbolic link
+    let shared = topdir.join(".Trash");
+    if let Ok(meta) = fs::symlink_metadata(&shared) {
+        if meta.is_dir() && (meta.mode() & 0o1000) != 0 {
+            ret.push(TrashDir { root: shared.join(uid.to_string()), topdir: Some(topdir.to_path_buf()) });
+        }
+    }
+    ret.push(TrashDir {
+        root: topdir.join(format!(".Trash-{}", uid)),
+        topdir: Some(topdir.to_path_buf()),
+    });
+    ret
+}
+
+/// Top directory of the volume containing `path`
+fn find_topdir(path: &Path) -> Result<PathBuf, Box<Error + Send + Sync>> {
+    let dev = fs::symlink_metadata(path)?.dev();
+    let mut topdir = path.to_path_buf();
+    while let Some(parent) = topdir.parent().map(|x| x.to_path_buf()) {
+        if fs::metadata(&parent)?.dev() != dev {
+            break;
+        }
+        topdir = parent;
+    }
+    Ok(topdir)
+}
+
+/// Trash directory to use for `path`
+fn trash_for(path: &Path) -> Result<TrashDir, Box<Error + Send + Sync>> {
+    let home = home_trash();
+    let path_dev = fs::symlink_metadata(path)?.dev();
+    // the home trash may not exist yet, compare with its nearest existing ancestor
+    let mut home_existing = home.root.clone();
+    while !home_existing.exists() {
+        match home_existing.parent().map(|x| x.to_path_buf()) {
+            Some(parent) => home_existing = parent,
+            None => break,
+        }
+    }
+    if fs::metadata(&home_existing)?.dev() == path_dev {
+        return Ok(home);
+    }
+    let topdir = find_topdir(path)?;
+    Ok(volume_trashes(&topdir).into_iter().next().unwrap())
+}
+
+/// Move file or directory to trash, return its new path in trash
+pub fn move_to_trash(path: &Path) -> Result<PathBuf, Box<Error + Send + Sync>> {
+    let path = if path.is_absolute() { path.to_path_buf() } else { fs::canonicalize(path)? };
+    let trash = trash_for(&path)?;
+    trash.ensure_dirs()?;
+    debug!("Trashing {:?} into {:?}", path, trash.root);
+
+    let original = match trash.topdir {
+        Some(ref topdir) => path.strip_prefix(topdir).map(|x| x.to_path_buf()).unwrap_or(path.clone()),
+        None => path.clone(),
+    };
+    let base_name = path.file_name()
+        .ok_or(ActionError::new("Cannot trash root directory"))?
+        .to_string_lossy().into_owned();
+
+    // reserve a name by creating the info file exclusively
+    let mut idx = 1;
+    loop {
+        let name = if idx == 1 { base_name.clone() } else { format!("{}.{}", base_name, idx) };
+        let info_path = trash.info_dir().join(format!("{}.trashinfo", name));
+        let target = trash.files_dir().join(&name);
+        match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(mut info_file) => {
+                if target.exists() {
+                    let _ = fs::remove_file(&info_path);
+                } else {
+                    write!(info_file, "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                           encode_path(&original),
+                           Local::now().format("%Y-%m-%dT%H:%M:%S"))?;
+                    if let Err(error) = fs::rename(&path, &target) {
+                        let _ = fs::remove_file(&info_path);
+                        return Err(Box::new(error));
+                    }
+                    return Ok(target);
+                }
+            },
+            Err(ref error) if error.kind() == ErrorKind::AlreadyExists => {},
+            Err(error) => return Err(Box::new(error)),
+        }
+        idx += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use actions::utils::trash::{parse_trashinfo, encode_path};
+
+    #[test]
+    fn parse_trashinfo_test() {
+        let (path, date) = parse_trashinfo("[Trash Info]\nPath=/home/user/foo%20bar.txt\nDeletionDate=2004-08-31T22:32:08\n").unwrap();
+        assert_eq!(path, Path::new("/home/user/foo bar.txt"));
+        assert_eq!(date.unwrap().format("%Y-%m-%d %H:%M:%S").to_string(), "2004-08-31 22:32:08");
+
+        let (path, date) = parse_trashinfo("[Trash Info]\nPath=dir/file\nDeletionDate=invalid\n").unwrap();
+        assert_eq!(path, Path::new("dir/file"));
+        assert!(date.is_none());
+
+        assert!(parse_trashinfo("[Other]\nPath=/foo\n").is_none());
+    }
+
+    #[test]
+    fn encode_path_test() {
+        for original in &["/home/user/100% done #1?.txt", "/tmp/a b/c%20d", "relative/ünicode"] {
+            let encoded = encode_path(Path::new(original));
+            assert!(!encoded.contains(' ') && !encoded.contains('#') && !encoded.contains('?'));
+            let (path, _) = parse_trashinfo(&format!("[Trash Info]\nPath={}\n", encoded)).unwrap();
+            assert_eq!(path, Path::new(original));
+        }
+    }
+}
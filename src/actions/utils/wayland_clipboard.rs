@@ -0,0 +1,123 @@
+//! Wayland clipboard, through wl-clipboard (wl-paste and wl-copy)
+
+use std::thread;
+use std::error::Error;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::Mutex;
+use std::process::{Command, Stdio};
+
+use actions::utils::clipboard_backend::{self, ClipboardBackend, Contents};
+
+pub struct WaylandBackend {
+    /// Text we set as sensitive, since wl-copy cannot serve the secret hint
+    last_sensitive: Mutex<Option<String>>,
+}
+
+impl WaylandBackend {
+    pub fn new() -> WaylandBackend {
+        WaylandBackend {
+            last_sensitive: Mutex::new(None),
+        }
+    }
+
+    fn read(&self, primary: bool, args: &[&str]) -> Option<Vec<u8>> {
+        let mut cmd = Command::new("wl-paste");
+        if primary {
+            cmd.arg("--primary");
+        }
+        match cmd.args(args).stderr(Stdio::null()).output() {
+            Ok(ref output) if output.status.success() => Some(output.stdout.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl ClipboardBackend for WaylandBackend {
+    fn name(&self) -> &'static str { "wayland" }
+
+    fn get_text(&self, primary: bool) -> Option<String> {
+        self.read(primary, &["--no-newline", "--type", "text"])
+            .map(|x| String::from_utf8_lossy(&x).into_owned())
+    }
+
+    fn targets(&self) -> Vec<String> {
+        match self.read(false, &["--list-types"]) {
+            Some(data) => String::from_utf8_lossy(&data).lines()
+                .map(|x| x.trim().to_string())
+                .filter(|x| x.len() > 0)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_contents(&self, target: &str) -> Option<Vec<u8>> {
+        self.read(false, &["--type", target])
+    }
+
+    /// wl-copy serves only one target
+    fn set_contents(&self, contents: Contents) -> Result<(), Box<Error + Send + Sync>> {
+        *self.last_sensitive.lock().unwrap() = None;
+        let (target, data) = match clipboard_backend::pick_target(contents) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        // wl-copy forks to serve in background
+        let mut child = Command::new("wl-copy").arg("--type").arg(&target)
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn()?;
+        if let Some(ref mut stdin) = child.stdin {
+            stdin.write_all(&data)?;
+        }
+        drop(child.stdin.take());
+        child.wait()?;
+        Ok(())
+    }
+
+    fn clear(&self) {
+        *self.last_sensitive.lock().unwrap() = None;
+        if let Err(error) = Command::new("wl-copy").arg("--clear").status() {
+            warn!("Unable to clear clipboard: {}", error);
+        }
+    }
+
+    fn set_text(&self, text: &str, sensitive: bool) -> Result<(), Box<Error + Send + Sync>> {
+        self.set_contents(clipboard_backend::text_contents(text))?;
+        if sensitive {
+            *self.last_sensitive.lock().unwrap() = Some(text.into());
+        }
+        Ok(())
+    }
+
+    fn is_sensitive(&self) -> bool {
+        let last_sensitive = self.last_sensitive.lock().unwrap().clone();
+        if let Some(text) = last_sensitive {
+            if self.get_text(false).map(|x| x == text).unwrap_or(false) {
+                return true;
+            }
+        }
+        clipboard_backend::has_secret_hint(self)
+    }
+
+    /// `wl-paste --watch` runs a command on every change, which prints a line for us
+    fn watch(&self, callback: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>> {
+        let mut child = Command::new("wl-paste").args(&["--watch", "echo", "changed"])
+            .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        thread::Builder::new().name("wayland-clipboard-watch".into()).spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if line.is_err() {
+                    break;
+                }
+                trace!("Wayland clipboard changed");
+                callback();
+            }
+            match child.wait() {
+                Ok(status) => warn!("wl-paste --watch exited: {}", status),
+                Err(error) => warn!("wl-paste --watch failed: {}", error),
+            }
+        })?;
+        Ok(())
+    }
+}
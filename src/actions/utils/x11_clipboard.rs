@@ -0,0 +1,183 @@
+//! X11 clipboard, accessed by xclip, watched by XFixes if built with x11-watch feature
+
+use std::error::Error;
+use std::io::prelude::*;
+use std::sync::Mutex;
+use std::process::{Command, Stdio};
+
+use actions::utils::clipboard_backend::{self, ClipboardBackend, Contents};
+#[cfg(feature="x11-watch")]
+use actions::utils::x11_watch;
+#[cfg(not(feature="x11-watch"))]
+use actions::ActionError;
+
+pub struct X11Backend {
+    /// X display name, default to $DISPLAY
+    display: Option<String>,
+    /// Text we set as sensitive, since xclip cannot serve the secret hint
+    last_sensitive: Mutex<Option<String>>,
+}
+
+impl X11Backend {
+    pub fn new(display: Option<String>) -> X11Backend {
+        X11Backend {
+            display: display,
+            last_sensitive: Mutex::new(None),
+        }
+    }
+
+    fn xclip(&self, primary: bool) -> Command {
+        let mut cmd = Command::new("xclip");
+        if let Some(ref display) = self.display {
+            cmd.arg("-display").arg(display);
+        }
+        cmd.arg("-selection").arg(if primary { "primary" } else { "clipboard" });
+        cmd
+    }
+
+    fn read(&self, primary: bool, target: Option<&str>) -> Option<Vec<u8>> {
+        let mut cmd = self.xclip(primary);
+        cmd.arg("-o");
+        if let Some(target) = target {
+            cmd.arg("-t").arg(target);
+        }
+        match cmd.stderr(Stdio::null()).output() {
+            Ok(ref output) if output.status.success() => Some(output.stdout.clone()),
+            _ => None,
+        }
+    }
+
+    /// Run xclip to serve `data`, which stays in background until another owner
+    fn write(&self, target: &str, data: &[u8]) -> Result<(), Box<Error + Send + Sync>> {
+        let mut child = self.xclip(false).arg("-t").arg(target).arg("-i")
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn()?;
+        if let Some(ref mut stdin) = child.stdin {
+            stdin.write_all(data)?;
+        }
+        drop(child.stdin.take());
+        child.wait()?;
+        Ok(())
+    }
+}
+
+impl ClipboardBackend for X11Backend {
+    fn name(&self) -> &'static str { "x11" }
+
+    fn get_text(&self, primary: bool) -> Option<String> {
+        self.read(primary, Some("UTF8_STRING"))
+            .map(|x| String::from_utf8_lossy(&x).into_owned())
+    }
+
+    fn targets(&self) -> Vec<String> {
+        match self.read(false, Some("TARGETS")) {
+            Some(data) => String::from_utf8_lossy(&data).lines()
+                .map(|x| x.trim().to_string())
+                .filter(|x| x.len() > 0)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_contents(&self, target: &str) -> Option<Vec<u8>> {
+        self.read(false, Some(target))
+    }
+
+    /// xclip serves only one target
+    fn set_contents(&self, contents: Contents) -> Result<(), Box<Error + Send + Sync>> {
+        *self.last_sensitive.lock().unwrap() = None;
+        match clipboard_backend::pick_target(contents) {
+            Some((target, data)) => self.write(&target, &data),
+            None => Ok(()),
+        }
+    }
+
+    fn clear(&self) {
+        *self.last_sensitive.lock().unwrap() = None;
+        if let Err(error) = self.write("UTF8_STRING", b"") {
+            warn!("Unable to clear clipboard: {}", error);
+        }
+    }
+
+    fn set_text(&self, text: &str, sensitive: bool) -> Result<(), Box<Error + Send + Sync>> {
+        self.set_contents(clipboard_backend::text_contents(text))?;
+        if sensitive {
+            *self.last_sensitive.lock().unwrap() = Some(text.into());
+        }
+        Ok(())
+    }
+
+    fn is_sensitive(&self) -> bool {
+        let last_sensitive = self.last_sensitive.lock().unwrap().clone();
+        if let Some(text) = last_sensitive {
+            if self.get_text(false).map(|x| x == text).unwrap_or(false) {
+                return true;
+            }
+        }
+        clipboard_backend::has_secret_hint(self)
+    }
+
+    #[cfg(feature="x11-watch")]
+    fn watch(&self, callback: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>> {
+        x11_watch::watch(self.display.as_ref().map(|x| x.as_str()), callback)
+    }
+
+    #[cfg(not(feature="x11-watch"))]
+    fn watch(&self, _: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>> {
+        Err(Box::new(ActionError::new("Watching X11 clipboard requires the x11-watch feature")))
+    }
+}
+
+
+#[cfg(all(test, feature="x11-watch"))]
+mod tests {
+    use std::thread;
+    use std::io::prelude::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use std::process::{Command, Stdio};
+    use actions::utils;
+    use actions::utils::clipboard_backend::ClipboardBackend;
+    use actions::utils::x11_clipboard::X11Backend;
+
+    #[test]
+    fn xvfb_watch_test() {
+        if !utils::command_exists("Xvfb") || !utils::command_exists("xclip") {
+            warn!("Xvfb or xclip not found, skip X11 clipboard test");
+            return;
+        }
+        let mut xvfb = Command::new("Xvfb").args(&["-displayfd", "1", "-nolisten", "tcp"])
+            .stdout(Stdio::piped()).stderr(Stdio::null())
+            .spawn().unwrap();
+        let mut display = String::new();
+        {
+            let stdout = xvfb.stdout.as_mut().unwrap();
+            let mut buf = [0u8; 1];
+            while stdout.read(&mut buf).unwrap_or(0) == 1 && buf[0] != b'\n' {
+                display.push(buf[0] as char);
+            }
+        }
+        let display = format!(":{}", display.trim());
+
+        let backend = X11Backend::new(Some(display.clone()));
+        let (send_ch, recv_ch) = mpsc::channel();
+        backend.watch(Box::new(move || { let _ = send_ch.send(()); })).unwrap();
+
+        backend.set_text("hello minions", false).unwrap();
+        assert!(recv_ch.recv_timeout(Duration::from_secs(5)).is_ok());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(backend.get_text(false), Some("hello minions".into()));
+        assert!(backend.targets().iter().any(|x| x == "UTF8_STRING"));
+        assert!(!backend.is_sensitive());
+
+        backend.set_text("secret", true).unwrap();
+        assert!(recv_ch.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(backend.is_sensitive());
+        backend.clear_if("secret");
+        assert!(recv_ch.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert_eq!(backend.get_text(false).unwrap_or(String::new()), "");
+
+        let _ = xvfb.kill();
+        let _ = xvfb.wait();
+    }
+}
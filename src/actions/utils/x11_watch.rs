@@ -0,0 +1,84 @@
+//! Watch X11 clipboard by XFixes selection notifications
+
+extern crate libc;
+
+use self::libc::{c_char, c_int, c_long, c_ulong, c_void};
+
+use std::ptr;
+use std::thread;
+use std::ffi::CString;
+use std::error::Error;
+
+use actions::ActionError;
+
+type Display = c_void;
+type Window = c_ulong;
+type Atom = c_ulong;
+
+/// XEvent is a union padded to 24 longs
+#[repr(C)]
+struct XEvent {
+    type_: c_int,
+    pad: [c_long; 23],
+}
+
+const XFIXES_SELECTION_NOTIFY: c_int = 0;
+const XFIXES_SET_SELECTION_OWNER_NOTIFY_MASK: c_ulong = 1;
+
+#[link(name="X11")]
+extern {
+    fn XOpenDisplay(display_name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XDefaultRootWindow(display: *mut Display) -> Window;
+    fn XInternAtom(display: *mut Display, atom_name: *const c_char, only_if_exists: c_int) -> Atom;
+    fn XNextEvent(display: *mut Display, event: *mut XEvent) -> c_int;
+}
+
+#[link(name="Xfixes")]
+extern {
+    fn XFixesQueryExtension(display: *mut Display, event_base: *mut c_int, error_base: *mut c_int) -> c_int;
+    fn XFixesSelectSelectionInput(display: *mut Display, window: Window, selection: Atom, event_mask: c_ulong);
+}
+
+/// Display connection owned by the watching thread
+struct DisplayHandle(*mut Display);
+unsafe impl Send for DisplayHandle {}
+
+/// Call `callback` in a background thread whenever CLIPBOARD owner changes on `display` (default $DISPLAY)
+pub fn watch(display: Option<&str>, callback: Box<Fn() + Send>) -> Result<(), Box<Error + Send + Sync>> {
+    let display = unsafe {
+        let name = match display {
+            Some(name) => Some(CString::new(name)?),
+            None => None,
+        };
+        XOpenDisplay(name.as_ref().map(|x| x.as_ptr()).unwrap_or(ptr::null()))
+    };
+    if display == ptr::null_mut() {
+        return Err(Box::new(ActionError::new("Unable to open X display")));
+    }
+    let mut event_base : c_int = 0;
+    let mut error_base : c_int = 0;
+    unsafe {
+        if XFixesQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+            XCloseDisplay(display);
+            return Err(Box::new(ActionError::new("XFixes extension unavailable")));
+        }
+        let clipboard = XInternAtom(display, b"CLIPBOARD\0".as_ptr() as *const c_char, 0);
+        XFixesSelectSelectionInput(display, XDefaultRootWindow(display), clipboard,
+                                   XFIXES_SET_SELECTION_OWNER_NOTIFY_MASK);
+    }
+
+    let handle = DisplayHandle(display);
+    thread::Builder::new().name("x11-clipboard-watch".into()).spawn(move || {
+        let handle = handle;
+        loop {
+            let mut event = XEvent { type_: 0, pad: [0; 23] };
+            unsafe { XNextEvent(handle.0, &mut event) };
+            if event.type_ == event_base + XFIXES_SELECTION_NOTIFY {
+                trace!("X11 clipboard owner changed");
+                callback();
+            }
+        }
+    })?;
+    Ok(())
+}
@@ -15,7 +15,9 @@ use self::url::form_urlencoded;
 
 use std::char;
 use std::io::Read;
-use mcore::action::{Action, ActionResult};
+use std::time::Duration;
+use mcore::action::{Action, ActionResult, ContentSupport};
+use mcore::content::ContentKind;
 use mcore::item::{Item, Icon};
 use actions::ActionError;
 
@@ -52,6 +54,15 @@ impl Action for Youdao {
 
     fn accept_text(&self) -> bool { true }
 
+    fn content_support(&self, kind: ContentKind) -> ContentSupport {
+        if kind == ContentKind::Text { ContentSupport::Supported } else { ContentSupport::Unknown }
+    }
+
+    fn uid(&self) -> String { "youdao".into() }
+
+    /// Translations hardly change
+    fn cache_ttl(&self) -> Option<Duration> { Some(Duration::from_secs(24 * 3600)) }
+
     fn run_text(&self, text: &str) -> ActionResult {
         let salt = "WTF";
         let mut hash = crypto::md5::Md5::new();
@@ -27,13 +27,15 @@ use frontend_gtk::ui::MinionsUI;
 use mcore::context::Context;
 use mcore::action::ActionResult;
 use mcore::item::Item;
+use actions::paste;
 
 const FILTER_TEXT_CLEAR_TIME: u32 = 1;
 
 #[derive(Clone)]
 enum Status {
     Initial,
-    Running(Rc<mpsc::Receiver<ActionResult>>),
+    /// Receivers of final result and partial items
+    Running(Rc<mpsc::Receiver<ActionResult>>, Rc<mpsc::Receiver<Vec<Item>>>),
     Error(Rc<Box<Error>>), // Rc is for Clone
     FilteringNone,
     FilteringEntering {
@@ -55,6 +57,13 @@ pub struct MinionsApp {
     ctx: Context,
 
     status: Status,
+
+    /// Refreshed items of cached action runs, with the run's uuid
+    refresh_send_ch: mpsc::Sender<(String, Vec<Item>)>,
+    refresh_recv_ch: mpsc::Receiver<(String, Vec<Item>)>,
+    /// Global search results, with the query
+    search_send_ch: mpsc::Sender<(String, Vec<Item>)>,
+    search_recv_ch: mpsc::Receiver<(String, Vec<Item>)>,
 }
 
 
@@ -108,12 +117,12 @@ impl MinionsApp {
                 self.ui.set_items(Vec::new(), -1, &self.ctx);
                 self.ui.set_spinning(false);
             },
-            Status::Running(_) => {
+            Status::Running(..) => {
                 self.ui.set_entry(None);
                 self.ui.set_filter_text("");
                 self.ui.set_action_name(None);
                 self.ui.set_reference(None);
-                self.ui.set_items(Vec::new(), -1, &self.ctx);
+                self.ui.set_items(self.ctx.partial_items.iter().collect(), -1, &self.ctx);
                 self.ui.set_spinning(true);
             },
             Status::Error(ref error) => {
@@ -181,6 +190,7 @@ impl MinionsApp {
             filter_indices: _
         } = self.status {
             if filter_text_lasttime.elapsed() >= std::time::Duration::new(FILTER_TEXT_CLEAR_TIME as u64, 0) {
+                self.ctx.clear_appended();
                 self.status = Status::FilteringNone;
                 self.update_ui();
             }
@@ -189,6 +199,7 @@ impl MinionsApp {
 
     fn process_keyevent_escape(&mut self) {
         trace!("Processing keyevent Escape");
+        self.ctx.clear_appended();
         self.status = match self.status {
             Status::Initial => {
                 debug!("Quit!");
@@ -199,8 +210,9 @@ impl MinionsApp {
                 self.ctx.reset();
                 Status::Initial
             },
-            Status::Running(_) => {
-                warn!("Drop thread");
+            Status::Running(..) => {
+                info!("Cancel running action");
+                self.ctx.cancel();
                 Status::FilteringNone
             }
             _ => Status::FilteringNone,
@@ -256,16 +268,25 @@ impl MinionsApp {
         self.status = match self.status.clone() {
             Status::FilteringEntering {
                 selected_idx,
-                filter_text: _,
+                mut filter_text,
                 filter_text_lasttime: _,
                 filter_indices
             } |
             Status::FilteringMoving {
                 selected_idx,
-                filter_text: _,
+                mut filter_text,
                 filter_indices
             } => {
-                if selected_idx < 0 {
+                // fallback items take the whole query, which may contain spaces
+                let fallback_selected = selected_idx >= 0 && {
+                    let idx = filter_indices[selected_idx as usize];
+                    self.ctx.is_appended(idx) && !self.ctx.list_items[idx].action_arg.is_none()
+                };
+                if fallback_selected {
+                    should_update_ui = true;
+                    filter_text.push(' ');
+                    self._make_status_filteringentering(filter_text)
+                } else if selected_idx < 0 {
                     warn!("No item to send");
                     self.status.clone()
                 } else {
@@ -288,8 +309,9 @@ impl MinionsApp {
         self.update_ui();
     }
 
-    fn _make_status_filteringentering(&self, text: String) -> Status {
+    fn _make_status_filteringentering(&mut self, text: String) -> Status {
         let filter_indices = self.ctx.filter(&text);
+        self.start_search(&text);
         let selected_idx = if filter_indices.len() == 0 { -1 } else { 0 };
 
         gtk::timeout_add_seconds(FILTER_TEXT_CLEAR_TIME, move || {
@@ -382,7 +404,7 @@ impl MinionsApp {
 
     fn process_running_callback(&mut self) {
         let mut res : Option<ActionResult> = None;
-        if let Status::Running(ref recv_ch) = self.status {
+        if let Status::Running(ref recv_ch, _) = self.status {
             if let Ok(res_) = recv_ch.try_recv() {
                 trace!("Received result on callback");
                 res = Some(res_);
@@ -408,6 +430,139 @@ impl MinionsApp {
         }
     }
 
+    fn process_partial_callback(&mut self) {
+        let mut items = Vec::new();
+        if let Status::Running(_, ref partial_ch) = self.status {
+            while let Ok(res) = partial_ch.try_recv() {
+                items.extend(res);
+            }
+        }
+        if items.len() > 0 {
+            trace!("Received {} partial items", items.len());
+            self.ctx.async_partial_callback(items);
+            self.update_ui();
+        }
+    }
+
+    fn process_refresh_callback(&mut self) {
+        let mut replaced = false;
+        while let Ok((uuid, items)) = self.refresh_recv_ch.try_recv() {
+            // indices in other status refer to current list_items
+            match self.status {
+                Status::FilteringNone | Status::FilteringEntering{..} | Status::FilteringMoving{..} => {
+                    if self.ctx.async_refresh_callback(&uuid, items) {
+                        replaced = true;
+                    }
+                },
+                _ => debug!("Not filtering, ignore refreshed items"),
+            }
+        }
+        if !replaced {
+            return;
+        }
+        trace!("Listing items refreshed");
+        self.status = match self.status.clone() {
+            Status::FilteringEntering { filter_text, .. } |
+            Status::FilteringMoving { filter_text, .. } => {
+                let filter_indices = if filter_text.len() > 0 {
+                    self.ctx.filter(&filter_text)
+                } else {
+                    (0..self.ctx.list_items.len()).collect()
+                };
+                Status::FilteringMoving {
+                    selected_idx: if filter_indices.len() == 0 { -1 } else { 0 },
+                    filter_text: filter_text,
+                    filter_indices: filter_indices,
+                }
+            },
+            _ => Status::FilteringNone,
+        };
+        self.update_ui();
+    }
+
+    /// Search providers in background, results are added by process_search_callback
+    fn start_search(&self, text: &str) {
+        let search_send_ch = self.search_send_ch.clone();
+        self.ctx.search_async(text, move |query: String, items: Vec<Item>| {
+            if search_send_ch.send((query, items)).is_ok() {
+                glib::idle_add( || {
+                    APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_search_callback() );
+                    Continue(false)
+                });
+            }
+        });
+    }
+
+    fn process_search_callback(&mut self) {
+        let filter_text = match self.status {
+            Status::FilteringEntering { ref filter_text, .. } |
+            Status::FilteringMoving { ref filter_text, .. } => filter_text.clone(),
+            _ => String::new(),
+        };
+        let mut received = false;
+        while let Ok((query, items)) = self.search_recv_ch.try_recv() {
+            if query == filter_text && self.ctx.search_callback(query, items) {
+                received = true;
+            }
+        }
+        if !received {
+            debug!("Global search results are outdated, ignore");
+            return;
+        }
+        trace!("Global search results received");
+        let new_indices = self.ctx.filter(&filter_text);
+        match self.status {
+            Status::FilteringEntering { ref mut selected_idx, ref mut filter_indices, .. } |
+            Status::FilteringMoving { ref mut selected_idx, ref mut filter_indices, .. } => {
+                if *selected_idx < 0 || *selected_idx as usize >= new_indices.len() {
+                    *selected_idx = if new_indices.len() == 0 { -1 } else { 0 };
+                }
+                *filter_indices = new_indices;
+            },
+            _ => {},
+        }
+        self.update_ui();
+    }
+
+    /// Run item (with text) in background, return the running status.
+    /// `force` to bypass cached results
+    fn start_running(&mut self, item: Item, text: Option<String>, force: bool) -> Status {
+        let (send_ch, recv_ch) = mpsc::channel::<ActionResult>();
+        let (partial_send_ch, partial_recv_ch) = mpsc::channel::<Vec<Item>>();
+        let partial = move |items: Vec<Item>| {
+            if partial_send_ch.send(items).is_ok() {
+                glib::idle_add( || {
+                    APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_partial_callback() );
+                    Continue(false)
+                });
+            }
+        };
+        let callback = move |res: ActionResult| {
+            if let Err(error) = send_ch.send(res) {
+                warn!("Unable to send to channel: {}", error);
+            } else {
+                glib::idle_add( || {
+                    APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_running_callback() );
+                    Continue(false)
+                });
+            }
+        };
+        let refresh_send_ch = self.refresh_send_ch.clone();
+        let refreshed = move |uuid: String, items: Vec<Item>| {
+            if refresh_send_ch.send((uuid, items)).is_ok() {
+                glib::idle_add( || {
+                    APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_refresh_callback() );
+                    Continue(false)
+                });
+            }
+        };
+        match text {
+            Some(text) => self.ctx.async_select_with_text(item, &text, force, partial, refreshed, callback),
+            None => self.ctx.async_select(item, force, partial, refreshed, callback),
+        };
+        Status::Running(Rc::new(recv_ch), Rc::new(partial_recv_ch))
+    }
+
     fn process_keyevent_enter(&mut self) {
         trace!("Processing keyevent Enter");
         self.status = match self.status.clone() {
@@ -430,21 +585,18 @@ impl MinionsApp {
                     let idx = filter_indices[selected_idx as usize];
                     let item = self.ctx.list_items[idx].clone();
 
-                    let (send_ch, recv_ch) = mpsc::channel::<ActionResult>();
                     if self.ctx.selectable(&item) {
-                        self.ctx.async_select(item, move |res: ActionResult| {
-                            if let Err(error) = send_ch.send(res) {
-                                warn!("Unable to send to channel: {}", error);
-                            } else {
-                                glib::idle_add( || {
-                                    APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_running_callback() );
-                                    Continue(false)
-                                });
-                            }
-                        });
-                        Status::Running(Rc::new(recv_ch))
+                        self.start_running(item, None, false)
                     } else if self.ctx.selectable_with_text(&item) {
                         Status::EnteringText(idx)
+                    } else if self.ctx.pasteable(&item) {
+                        if let Err(error) = self.ctx.paste(&item) {
+                            warn!("Unable to paste item: {}", error);
+                            Status::Error(Rc::new(error))
+                        } else {
+                            self.ui.window.hide();
+                            Status::Initial
+                        }
                     } else {
                         warn!("Item not selectable with or without text");
                         self.status.clone()
@@ -454,23 +606,54 @@ impl MinionsApp {
             Status::EnteringText(idx) => {
                 let text = self.ui.get_entry_text();
                 let item = self.ctx.list_items[idx].clone();
-                let (send_ch, recv_ch) = mpsc::channel::<ActionResult>();
+                self.start_running(item, Some(text), false)
+            },
+            status @ _ => status,
+        };
+        self.update_ui();
+    }
 
-                self.ctx.async_select_with_text(item, &text, move |res: ActionResult| {
-                    if let Err(error) = send_ch.send(res) {
-                        warn!("Unable to send to channel: {}", error);
+    /// Run selected item again, bypassing cached results
+    fn process_keyevent_refresh(&mut self) {
+        trace!("Process keyevent refresh");
+        let mut should_update_ui = false;
+        self.status = match self.status.clone() {
+            Status::FilteringEntering {
+                selected_idx,
+                filter_text: _,
+                filter_text_lasttime: _,
+                filter_indices,
+            } |
+            Status::FilteringMoving {
+                selected_idx,
+                filter_text: _,
+                filter_indices,
+            } => {
+                if selected_idx < 0 {
+                    warn!("No item to refresh");
+                    self.status.clone()
+                } else {
+                    let item = self.ctx.list_items[filter_indices[selected_idx as usize]].clone();
+                    if self.ctx.selectable(&item) {
+                        should_update_ui = true;
+                        self.start_running(item, None, true)
                     } else {
-                        glib::idle_add( || {
-                            APP.with(move |app| app.borrow_mut().as_mut().unwrap().process_running_callback() );
-                            Continue(false)
-                        });
+                        warn!("Item not selectable");
+                        self.status.clone()
                     }
-                });
-                Status::Running(Rc::new(recv_ch))
+                }
+            },
+            Status::EnteringText(idx) => {
+                should_update_ui = true;
+                let text = self.ui.get_entry_text();
+                let item = self.ctx.list_items[idx].clone();
+                self.start_running(item, Some(text), true)
             },
             status @ _ => status,
         };
-        self.update_ui();
+        if should_update_ui {
+            self.update_ui();
+        }
     }
 
     fn process_keyevent_copy(&mut self) {
@@ -525,6 +708,9 @@ impl MinionsApp {
         } else if key == 'c' as u32 && modi == gdk::CONTROL_MASK {
             self.process_keyevent_copy();
             Inhibit(true)
+        } else if key == 'r' as u32 && modi == gdk::CONTROL_MASK {
+            self.process_keyevent_refresh();
+            Inhibit(true)
         } else if key == gdk::enums::key::Down {
             self.process_keyevent_move(1);
             Inhibit(true)
@@ -532,7 +718,8 @@ impl MinionsApp {
             self.process_keyevent_move(-1);
             Inhibit(true)
         } else if let Some(ch) = gdk::keyval_to_unicode(key) {
-            if ch.is_alphabetic() {
+            // not only letters, so that fallback items get URLs, paths or commands
+            if !ch.is_whitespace() && !ch.is_control() {
                 self.process_keyevent_char(ch);
             } else {
                 trace!("Ignore char: {}", ch);
@@ -545,6 +732,7 @@ impl MinionsApp {
 
     fn reset_window(&mut self, send_clipboard: bool) {
         trace!("Resetting window: {}", send_clipboard);
+        paste::remember_window();
         self.ctx.reset();
         self.status = Status::Initial;
         if send_clipboard {
@@ -559,10 +747,16 @@ impl MinionsApp {
     }
 
     pub fn new(config: toml::Value) -> &'static thread::LocalKey<RefCell<Option<MinionsApp>>> {
+        let (refresh_send_ch, refresh_recv_ch) = mpsc::channel::<(String, Vec<Item>)>();
+        let (search_send_ch, search_recv_ch) = mpsc::channel::<(String, Vec<Item>)>();
         let app = MinionsApp {
             ui: MinionsUI::new(),
             ctx: Context::new(config),
             status: Status::Initial,
+            refresh_send_ch: refresh_send_ch,
+            refresh_recv_ch: refresh_recv_ch,
+            search_send_ch: search_send_ch,
+            search_recv_ch: search_recv_ch,
         };
         app.update_ui();
         app.ui.window.hide();
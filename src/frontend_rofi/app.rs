@@ -14,6 +14,7 @@ use std::process::{Command, Stdio};
 
 use mcore::context::Context;
 use mcore::item::Item;
+use actions::paste;
 
 use frontend_rofi::utils;
 
@@ -22,6 +23,8 @@ enum State {
     Filtering(i32, String),
     EnteringText(usize),
     Exiting,
+    /// Item pasted, exit without showing again
+    Pasted,
 }
 
 pub struct MinionsApp {
@@ -42,7 +45,8 @@ impl MinionsApp {
            .arg("-dmenu")
            .arg("-width").arg((-ROFI_WIDTH-2).to_string())
            .arg("-p").arg(&prompt)
-           .arg("-format").arg("f");
+           .arg("-format").arg("f")
+           .arg("-kb-custom-4").arg("Control+r");
         debug!("Executing: {:?}", cmd);
 
         let mut child = cmd.spawn()?;
@@ -54,7 +58,11 @@ impl MinionsApp {
 
         Ok (match status {
             0 => { // enter
-                self.ctx.select_with_text(item, &stdout_str)?;
+                self.ctx.select_with_text(item, &stdout_str, false)?;
+                State::Filtering(-1, String::new())
+            },
+            13 => { // control-r, run bypassing cached results
+                self.ctx.select_with_text(item, &stdout_str, true)?;
                 State::Filtering(-1, String::new())
             },
             1 => { // esc
@@ -75,7 +83,6 @@ impl MinionsApp {
            .arg("-dmenu")
            .arg("-i")  // case insensitive
            .arg("-matching").arg("fuzzy")
-           .arg("-no-custom")
            .arg("-markup-rows")
            .arg("-width").arg((-ROFI_WIDTH-2).to_string())
            .arg("-p").arg("Minions: ")
@@ -85,7 +92,8 @@ impl MinionsApp {
            .arg("-kb-custom-1").arg("space")
            .arg("-kb-row-tab").arg("") // disable default Tab
            .arg("-kb-custom-2").arg("Tab")
-           .arg("-kb-custom-3").arg("Control+c");
+           .arg("-kb-custom-3").arg("Control+c")
+           .arg("-kb-custom-4").arg("Control+r");
         if let Some(ref data) = self.ctx.reference {
             let msg = utils::format_reference_info(data, ROFI_WIDTH);
             cmd.arg("-mesg").arg(&msg);
@@ -113,7 +121,20 @@ impl MinionsApp {
         let mut stdout_str: Vec<&str> = stdout_str.splitn(2, '|').collect();
 
         let filter_str = stdout_str.pop().unwrap().trim();
-        let selected_idx: usize = stdout_str.pop().unwrap().parse()?;
+        let selected_idx: i32 = stdout_str.pop().unwrap().parse()?;
+        if selected_idx < 0 {
+            // custom input matching nothing, show again with global search results
+            // and fallback items for it
+            let indices = self.ctx.filter(filter_str);
+            return Ok(match indices.iter().find(|x| self.ctx.is_appended(**x)) {
+                Some(idx) => State::Filtering(*idx as i32, filter_str.into()),
+                None => {
+                    warn!("No result or fallback item for {}", filter_str);
+                    State::Filtering(-1, filter_str.into())
+                },
+            });
+        }
+        let selected_idx = selected_idx as usize;
         let selected_item = self.ctx.list_items[selected_idx].clone();
 
         Ok( match status {
@@ -141,12 +162,24 @@ impl MinionsApp {
                 }
                 State::Filtering(selected_idx as i32, filter_str.into())
             }
+            13 => { // control-r, run bypassing cached results
+                if self.ctx.selectable(&selected_item) {
+                    self.ctx.select(selected_item, true)?;
+                    State::Filtering(-1, String::new())
+                } else {
+                    warn!("Item {} not selectable", selected_item.title);
+                    State::Filtering(selected_idx as i32, filter_str.into())
+                }
+            },
             0 => { // enter
                 if self.ctx.selectable(&selected_item) {
-                    self.ctx.select(selected_item)?;
+                    self.ctx.select(selected_item, false)?;
                     State::Filtering(-1, String::new())
                 } else if self.ctx.selectable_with_text(&selected_item) {
                     State::EnteringText(selected_idx)
+                } else if self.ctx.pasteable(&selected_item) {
+                    self.ctx.paste(&selected_item)?;
+                    State::Pasted
                 } else {
                     warn!("Item {} not selectable", selected_item.title);
                     State::Filtering(selected_idx as i32, filter_str.into())
@@ -184,6 +217,10 @@ impl MinionsApp {
                     self.ctx.reset();
                     self.state = State::Filtering(-1, String::new());
                 },
+                Ok(State::Pasted) => {
+                    info!("Pasted, exit!");
+                    break;
+                },
                 Ok(State::Exiting) => {
                     if exiting_count == 0 {
                         self.ctx.reset();
@@ -200,6 +237,7 @@ impl MinionsApp {
                 }
             };
         }
+        paste::wait();
     }
 
 
@@ -208,6 +246,7 @@ impl MinionsApp {
             ctx: Context::new(config),
             state: State::Filtering(-1, String::new()),
         };
+        paste::remember_window();
         if from_clipboard {
             if let Err(error) = app.ctx.quicksend_from_clipboard() {
                 warn!("Unable to get content from clipboard: {}", error);
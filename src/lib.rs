@@ -6,6 +6,8 @@ extern crate log;
 
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate lazy_static;
 extern crate toml;
 
 #[cfg(feature="use-gtk")]
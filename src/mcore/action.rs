@@ -7,7 +7,10 @@
 
 use std;
 use std::error::Error;
+use std::time::Duration;
+use std::sync::atomic::AtomicBool;
 use mcore::item::Item;
+use mcore::content::ContentKind;
 
 #[derive(Debug, Clone)]
 pub enum ActionArg {
@@ -25,6 +28,31 @@ impl ActionArg {
     }
 }
 
+/// How well an action handles some MIME type, better first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MimeSupport {
+    /// It's the default application for this type
+    Default,
+    /// It declares support for this type
+    Supported,
+    /// Don't know
+    Unknown,
+    /// It declares some types, but not this one
+    Unsupported,
+}
+
+/// How well an action handles some content kind of text, better first.
+/// Unlike MIME types, there's no default action for a content kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContentSupport {
+    /// It's meant for this kind
+    Supported,
+    /// Don't know
+    Unknown,
+    /// It handles some kinds, but not this one
+    Unsupported,
+}
+
 pub type ActionResult = Result<Vec<Item>, Box<Error + Send + Sync>>;
 
 /// The general action type
@@ -49,9 +77,32 @@ pub trait Action {
         }
     }
 
+    /// How well this action handles path input of given MIME type
+    fn mime_support(&self, &str) -> MimeSupport { MimeSupport::Unknown }
+
+    /// How well this action handles text input of given content kind
+    fn content_support(&self, ContentKind) -> ContentSupport { ContentSupport::Unknown }
+
     /// Whether this action is supposed to return items
     fn should_return_items(&self) -> bool { true }
 
+    /// Whether its children are searched from the root query
+    fn is_search_provider(&self) -> bool { false }
+
+    /// Children to search for query, which are then filtered by the caller.
+    /// Default to all children
+    fn search(&self, &str) -> ActionResult { self.run() }
+
+    /// Whether `search` results depend on the query. If not, they are
+    /// cached as results of `run`, once for all queries
+    fn search_uses_query(&self) -> bool { false }
+
+    /// Identifies this action in the result cache, default to its title
+    fn uid(&self) -> String { self.get_item().title }
+
+    /// How long returned items are cached, None to disable
+    fn cache_ttl(&self) -> Option<Duration> { None }
+
     /// Auto-complete (suggest) input test
     fn complete_text(&self, &str) -> Result<Vec<String>, Box<Error>> {
         Ok(Vec::new())
@@ -74,5 +125,12 @@ pub trait Action {
             ActionArg::Path(ref path) => self.run_path(&path),
         }
     }
+
+    /// Run using ActionArg, passing items to the sink as soon as they are available
+    /// and stopping early once cancelled. The result still contains all items.
+    /// Actions which produce items at once do not need to override this
+    fn run_arg_stream(&self, arg: &ActionArg, _: &Fn(Vec<Item>), _: &AtomicBool) -> ActionResult {
+        self.run_arg(arg)
+    }
 }
 
@@ -0,0 +1,231 @@
+//! In-memory cache of action results, by action uid and input
+
+use toml;
+
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+
+use mcore::action::{Action, ActionArg, ActionResult};
+use mcore::item::Item;
+
+/// Input of cached results
+#[derive(Debug, Clone)]
+pub enum CacheInput {
+    Arg(ActionArg),
+    /// Global search query
+    Search(String),
+}
+
+impl CacheInput {
+    fn key(&self) -> String {
+        match *self {
+            CacheInput::Arg(ActionArg::None) => String::new(),
+            CacheInput::Arg(ActionArg::Text(ref text)) => format!("text:{}", text),
+            CacheInput::Arg(ActionArg::Path(ref path)) => format!("path:{}", path.to_string_lossy()),
+            CacheInput::Search(ref query) => format!("search:{}", query),
+        }
+    }
+
+    fn run(&self, action: &Action) -> ActionResult {
+        match *self {
+            CacheInput::Arg(ref arg) => action.run_arg(arg),
+            CacheInput::Search(ref query) => action.search(query),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Config {
+    enabled: bool,
+    background_refresh: bool,
+    /// Oldest entries are dropped beyond this
+    max_entries: usize,
+    /// TTL in seconds by action name (title), overriding the action's own, 0 to disable
+    ttl: Option<HashMap<String, u64>>,
+}
+
+struct Entry {
+    time: Instant,
+    items: Vec<Item>,
+}
+
+pub struct ResultCache {
+    enabled: bool,
+    /// Serve expired results, while refreshing them in background
+    background_refresh: bool,
+    max_entries: usize,
+    ttl_overrides: HashMap<String, u64>,
+
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Keys being refreshed in background
+    refreshing: Mutex<HashSet<String>>,
+}
+
+impl ResultCache {
+    /// Disabled if `config` is None
+    pub fn new(config: Option<toml::Value>) -> ResultCache {
+        let mut ret = ResultCache {
+            enabled: false,
+            background_refresh: false,
+            max_entries: 0,
+            ttl_overrides: HashMap::new(),
+            entries: Mutex::new(HashMap::new()),
+            refreshing: Mutex::new(HashSet::new()),
+        };
+        match config.map(|x| x.try_into::<Config>()) {
+            Some(Ok(config)) => {
+                ret.enabled = config.enabled;
+                ret.background_refresh = config.background_refresh;
+                ret.max_entries = config.max_entries;
+                ret.ttl_overrides = config.ttl.unwrap_or(HashMap::new());
+            },
+            Some(Err(error)) => warn!("Error loading cache config: {}", error),
+            None => {},
+        }
+        ret
+    }
+
+    /// How long results of `action` are fresh, None if not cached
+    fn ttl(&self, action: &Action) -> Option<Duration> {
+        if !self.enabled || !action.should_return_items() {
+            return None;
+        }
+        match self.ttl_overrides.get(&action.get_item().title) {
+            Some(&0) => None,
+            Some(&seconds) => Some(Duration::from_secs(seconds)),
+            None => action.cache_ttl(),
+        }
+    }
+
+    /// Sensitive items are never cached
+    fn put(&self, key: String, items: &[Item]) {
+        if items.iter().any(|x| x.sensitive) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            let oldest = entries.iter().min_by_key(|x| x.1.time).map(|x| x.0.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, Entry {
+            time: Instant::now(),
+            items: items.to_vec(),
+        });
+    }
+
+    /// Get results of `action` with `input`, served from cache unless `force`.
+    /// `run` is called if not cached. If background refresh is enabled,
+    /// expired results are served as well, and `refreshed` is called with new results
+    pub fn run<F>(cache: &Arc<ResultCache>, action: &Arc<Box<Action + Sync + Send>>, input: CacheInput,
+                  force: bool, run: F, refreshed: Option<Box<Fn(Vec<Item>) + Send>>) -> ActionResult
+    where F: FnOnce() -> ActionResult {
+        let ttl = match cache.ttl(&***action) {
+            Some(ttl) => ttl,
+            None => return run(),
+        };
+        let key = format!("{}\0{}", action.uid(), input.key());
+
+        if !force {
+            let cached = cache.entries.lock().unwrap().get(&key)
+                .map(|x| (x.time.elapsed() < ttl, x.items.clone()));
+            match cached {
+                Some((true, items)) => {
+                    debug!("Serving cached results for {:?}", input);
+                    return Ok(items);
+                },
+                Some((false, items)) => if cache.background_refresh {
+                    debug!("Serving expired results for {:?}, refreshing", input);
+                    if cache.refreshing.lock().unwrap().insert(key.clone()) {
+                        let cache = cache.clone();
+                        let action = action.clone();
+                        thread::spawn(move || {
+                            match input.run(&**action) {
+                                Ok(items) => {
+                                    cache.put(key.clone(), &items);
+                                    if let Some(refreshed) = refreshed {
+                                        refreshed(items);
+                                    }
+                                },
+                                Err(error) => warn!("Unable to refresh cached results: {}", error),
+                            }
+                            cache.refreshing.lock().unwrap().remove(&key);
+                        });
+                    }
+                    return Ok(items);
+                },
+                None => {},
+            }
+        }
+
+        let result = run();
+        if let Ok(ref items) = result {
+            cache.put(key, items);
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use toml;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use mcore::action::{Action, ActionArg, ActionResult};
+    use mcore::item::Item;
+    use mcore::cache::{ResultCache, CacheInput};
+
+    struct TestAction {
+        name: &'static str,
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl Action for TestAction {
+        fn get_item(&self) -> Item { Item::new(self.name) }
+        fn accept_text(&self) -> bool { true }
+        fn cache_ttl(&self) -> Option<Duration> { Some(Duration::from_secs(60)) }
+        fn run_text(&self, text: &str) -> ActionResult {
+            let runs = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(vec![Item::new_text_item(&format!("{} {}", text, runs))])
+        }
+    }
+
+    fn run(cache: &Arc<ResultCache>, action: &Arc<Box<Action + Sync + Send>>, text: &str, force: bool) -> String {
+        let input = CacheInput::Arg(ActionArg::Text(text.into()));
+        let items = ResultCache::run(cache, action, input, force, || action.run_text(text), None).unwrap();
+        items[0].title.clone()
+    }
+
+    #[test]
+    fn result_cache_test() {
+        let config = r#"
+            enabled = true
+            background_refresh = false
+            max_entries = 2
+            [ttl]
+            "Uncached" = 0
+        "#.parse::<toml::Value>().unwrap();
+        let cache = Arc::new(ResultCache::new(Some(config)));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let action : Arc<Box<Action + Sync + Send>> = Arc::new(Box::new(TestAction { name: "Cached", runs: runs.clone() }));
+        assert_eq!(run(&cache, &action, "a", false), "a 1");
+        assert_eq!(run(&cache, &action, "a", false), "a 1");
+        assert_eq!(run(&cache, &action, "b", false), "b 2");
+        // forced refresh bypasses and updates the cache
+        assert_eq!(run(&cache, &action, "a", true), "a 3");
+        assert_eq!(run(&cache, &action, "a", false), "a 3");
+        // "b" is the oldest one, dropped for "c"
+        assert_eq!(run(&cache, &action, "c", false), "c 4");
+        assert_eq!(run(&cache, &action, "a", false), "a 3");
+        assert_eq!(run(&cache, &action, "b", false), "b 5");
+
+        let action : Arc<Box<Action + Sync + Send>> = Arc::new(Box::new(TestAction { name: "Uncached", runs: runs.clone() }));
+        assert_eq!(run(&cache, &action, "a", false), "a 6");
+        assert_eq!(run(&cache, &action, "a", false), "a 7");
+    }
+}
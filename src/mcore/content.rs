@@ -0,0 +1,156 @@
+//! Detect what kind of content some text is, for quick-send routing
+
+extern crate url;
+extern crate serde_json;
+
+use self::url::Url;
+
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    #[serde(rename = "url")]
+    Url,
+    #[serde(rename = "email")]
+    Email,
+    /// Absolute or relative file path, may not exist
+    #[serde(rename = "path")]
+    Path,
+    /// IPv4 or IPv6 address, optionally with port
+    #[serde(rename = "ip")]
+    IpAddress,
+    /// Hex color like #ff0000, or CSS rgb()/hsl()
+    #[serde(rename = "color")]
+    Color,
+    /// Number or arithmetic expression
+    #[serde(rename = "expression")]
+    Expression,
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "text")]
+    Text,
+}
+
+fn is_url(text: &str) -> bool {
+    if text.starts_with("www.") && text.len() > 4 {
+        return true;
+    }
+    text.contains("://") && Url::parse(text).map(|x| x.has_host() || x.scheme() == "file").unwrap_or(false)
+}
+
+fn is_email(text: &str) -> bool {
+    let text = if text.starts_with("mailto:") { &text[7..] } else { text };
+    let mut parts = text.splitn(2, '@');
+    let (local, domain) = match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => (local, domain),
+        _ => return false,
+    };
+    local.len() > 0 && !local.contains(|c: char| c.is_whitespace() || c == '@') &&
+        domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') &&
+        domain.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+}
+
+fn is_path(text: &str) -> bool {
+    text == "~" || ["/", "~/", "./", "../"].iter().any(|x| text.starts_with(x))
+}
+
+fn is_ip_address(text: &str) -> bool {
+    text.parse::<IpAddr>().is_ok() || text.parse::<SocketAddr>().is_ok()
+}
+
+fn is_color(text: &str) -> bool {
+    if text.starts_with('#') {
+        let hex = &text[1..];
+        return [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_digit(16));
+    }
+    let lower = text.to_lowercase();
+    ["rgb(", "rgba(", "hsl(", "hsla("].iter().any(|x| lower.starts_with(x)) && lower.ends_with(')')
+}
+
+fn is_expression(text: &str) -> bool {
+    if text.starts_with("0x") && text.len() > 2 && text[2..].chars().all(|c| c.is_digit(16)) {
+        return true;
+    }
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            },
+            '0' ... '9' | '.' | '+' | '-' | '*' | '/' | '%' | '^' | ' ' => {},
+            _ => return false,
+        }
+    }
+    depth == 0 && text.contains(|c: char| c.is_digit(10))
+}
+
+fn is_json(text: &str) -> bool {
+    (text.starts_with('{') || text.starts_with('[')) &&
+        serde_json::from_str::<serde_json::Value>(text).is_ok()
+}
+
+/// Classify text, surrounding whitespaces ignored
+pub fn classify(text: &str) -> ContentKind {
+    let text = text.trim();
+    if text.len() == 0 {
+        return ContentKind::Text;
+    }
+    if is_json(text) {
+        return ContentKind::Json;
+    }
+    if text.contains('\n') {
+        return ContentKind::Text;
+    }
+    if is_url(text) {
+        ContentKind::Url
+    } else if is_email(text) {
+        ContentKind::Email
+    } else if is_path(text) {
+        ContentKind::Path
+    } else if is_ip_address(text) {
+        ContentKind::IpAddress
+    } else if is_color(text) {
+        ContentKind::Color
+    } else if is_expression(text) {
+        ContentKind::Expression
+    } else {
+        ContentKind::Text
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use mcore::content::{classify, ContentKind};
+
+    #[test]
+    fn classify_test() {
+        assert_eq!(classify("https://github.com/blahgeek/Minions"), ContentKind::Url);
+        assert_eq!(classify(" www.rust-lang.org\n"), ContentKind::Url);
+        assert_eq!(classify("file:///etc/hosts"), ContentKind::Url);
+        assert_eq!(classify("i@blahgeek.com"), ContentKind::Email);
+        assert_eq!(classify("mailto:i@blahgeek.com"), ContentKind::Email);
+        assert_eq!(classify("/usr/share/applications"), ContentKind::Path);
+        assert_eq!(classify("~/Downloads/a b.pdf"), ContentKind::Path);
+        assert_eq!(classify("192.168.1.1"), ContentKind::IpAddress);
+        assert_eq!(classify("10.0.0.1:8080"), ContentKind::IpAddress);
+        assert_eq!(classify("::1"), ContentKind::IpAddress);
+        assert_eq!(classify("#FF8800"), ContentKind::Color);
+        assert_eq!(classify("rgba(0, 0, 0, 0.5)"), ContentKind::Color);
+        assert_eq!(classify("42"), ContentKind::Expression);
+        assert_eq!(classify("(1 + 2.5) * 3^2"), ContentKind::Expression);
+        assert_eq!(classify("0xdeadbeef"), ContentKind::Expression);
+        assert_eq!(classify("{\"a\": [1, 2]}"), ContentKind::Json);
+        assert_eq!(classify("[1,\n 2]"), ContentKind::Json);
+        assert_eq!(classify("hello world"), ContentKind::Text);
+        assert_eq!(classify("a@b"), ContentKind::Text);
+        assert_eq!(classify("#hashtag"), ContentKind::Text);
+        assert_eq!(classify("(1 + 2"), ContentKind::Text);
+        assert_eq!(classify("-"), ContentKind::Text);
+        assert_eq!(classify("line one\nline two"), ContentKind::Text);
+    }
+}
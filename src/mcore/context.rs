@@ -5,24 +5,27 @@
 * @Last Modified time: 2017-07-15
 */
 
-#[cfg(feature="use-gtk")]
-extern crate gtk;
-
-#[cfg(feature="use-gtk")]
-extern crate gdk;
-
 extern crate uuid;
 use self::uuid::Uuid;
 
 use toml;
 
+use std::cmp;
 use std::thread;
 use std::error::Error;
+use std::time::Duration;
 use std::sync::Arc;
-use mcore::action::{Action, ActionArg, ActionResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use mcore::action::{Action, ActionArg, ActionResult, MimeSupport, ContentSupport};
 use mcore::item::{Item, ItemData};
 use mcore::fuzzymatch::fuzzymatch;
+use mcore::content::{self, ContentKind};
+use mcore::search::GlobalSearch;
+use mcore::cache::{ResultCache, CacheInput};
+use mcore::mime;
 use actions;
+use actions::clipboard_backend::{self, ClipboardBackend};
+use actions::paste::{self, PasteMethod};
 
 
 pub struct Context {
@@ -30,13 +33,37 @@ pub struct Context {
     pub reference: Option<ItemData>,
     /// Candidates items list
     pub list_items: Vec<Item>,
+    /// Number of items appended to list_items by filter, at the end
+    appended_count: usize,
+    /// Whether list_items is the root list (of all actions)
+    at_root: bool,
+    /// Uuid of the action run which list_items come from, for background refresh
+    list_uuid: Option<String>,
 
     /// Stack of history items, init with empty stack
     /// Calling the last item's action would yields list_items
     history_items: Vec<Item>,
 
+    /// Items received so far from the running action
+    pub partial_items: Vec<Item>,
+    /// Cancel flag of the running action
+    cancel_flag: Arc<AtomicBool>,
+    /// Uuid of the running (or last) action run
+    running_uuid: String,
+
     /// Cached all actions
     all_actions: Vec<Arc<Box<Action + Sync + Send>>>,
+    /// Actions of fallback items, appended to list_items by filter
+    fallback_actions: Vec<Arc<Box<Action + Sync + Send>>>,
+    /// Search providers for root queries
+    global_search: GlobalSearch,
+    /// Last global search query and its results, appended by filter
+    search_results: Option<(String, Vec<Item>)>,
+    /// Results of slow actions
+    cache: Arc<ResultCache>,
+
+    /// Clear sensitive content from clipboard after this many seconds, 0 to disable
+    clear_sensitive_after: u32,
 }
 
 
@@ -44,11 +71,30 @@ impl Context {
 
     /// Create context with initial items
     pub fn new(config: toml::Value) -> Context {
+        let clear_sensitive_after = config.get("clipboard_history")
+            .and_then(|x| x.get("clear_sensitive_after"))
+            .and_then(|x| x.as_integer())
+            .unwrap_or(0);
+        let all_actions = actions::get_actions(config.clone());
+        let fallback_actions = actions::get_fallback_actions(&config);
+        let cache = Arc::new(ResultCache::new(config.get("cache").cloned()));
+        let global_search = GlobalSearch::new(config.get("global_search").cloned(), &all_actions, cache.clone());
         let mut ctx = Context {
             reference: None,
             list_items: Vec::new(),
+            appended_count: 0,
+            at_root: true,
+            list_uuid: None,
             history_items: Vec::new(),
-            all_actions: actions::get_actions(config),
+            partial_items: Vec::new(),
+            cancel_flag: Arc::new(AtomicBool::new(false)),
+            running_uuid: String::new(),
+            all_actions: all_actions,
+            fallback_actions: fallback_actions,
+            global_search: global_search,
+            search_results: None,
+            cache: cache,
+            clear_sensitive_after: if clear_sensitive_after > 0 { clear_sensitive_after as u32 } else { 0 },
         };
         ctx.reset();
         ctx
@@ -57,93 +103,199 @@ impl Context {
     /// Reset context to initial state
     pub fn reset(&mut self) {
         self.reference = None;
-        self.list_items = self.all_actions.iter()
+        let items = self.all_actions.iter()
             .filter(|action| {
                 action.accept_nothing() || action.accept_text()
             })
             .map(|action| Item::new_action_item(action.clone()))
             .collect();
+        self.set_list_items(items);
         self.list_items.sort_by_key(|item| item.priority );
+        self.at_root = true;
         self.history_items = Vec::new();
+        self.search_results = None;
+        self.global_search.reset();
     }
 
-    /// Initialize quicksend item from clipboard
-    #[cfg(not(feature="use-gtk"))]
-    pub fn quicksend_from_clipboard(&mut self) -> Result<(), Box<Error + Sync + Send>> {
-        let clip = Command::new("xclip").arg("-o").output()?;
-        let clip = String::from_utf8(clip.stdout)?;
-        if clip.len() > 0 {
-            let clip_item = Item::new_text_item(&clip);
-            self.quicksend(clip_item)
-        } else {
-            Ok(())
+    /// Replace list_items, which is no longer the root list
+    fn set_list_items(&mut self, items: Vec<Item>) {
+        self.list_items = items;
+        self.appended_count = 0;
+        self.at_root = false;
+        self.list_uuid = None;
+    }
+
+    fn clipboard() -> Result<Arc<Box<ClipboardBackend>>, Box<Error + Sync + Send>> {
+        match clipboard_backend::get() {
+            Some(backend) => Ok(backend),
+            None => Err(From::from("No clipboard available")),
         }
     }
 
-    #[cfg(feature="use-gtk")]
+    /// Initialize quicksend item from clipboard, PRIMARY first
     pub fn quicksend_from_clipboard(&mut self) -> Result<(), Box<Error + Sync + Send>> {
-        for clipboard in vec!["PRIMARY", "CLIPBOARD"] {
-            let clipboard = gtk::Clipboard::get(&gdk::Atom::intern(&clipboard));
-            let content = clipboard.wait_for_text();
-
-            if let Some(text) = content {
-                trace!("Clipboard content from: {:?}", text);
-                return self.quicksend(Item::new_text_item(&text));
+        let backend = Context::clipboard()?;
+        for primary in vec![true, false] {
+            match backend.get_text(primary) {
+                Some(ref text) if text.len() > 0 => {
+                    trace!("Clipboard content from: {:?}", text);
+                    return self.quicksend(Item::new_text_item(text));
+                },
+                _ => {},
             }
         }
         Ok(())
     }
 
-    #[cfg(not(feature="use-gtk"))]
-    pub fn copy_content_to_clipboard(&self, item: &Item) -> Result<(), Box<Error + Sync + Send>> {
-        let s : &str = match item.data {
+    fn item_text(item: &Item) -> &str {
+        match item.data {
             Some(ItemData::Text(ref text)) => text,
-            Some(ItemData::Path(ref path)) => &path.to_str().unwrap(),
+            Some(ItemData::Path(ref path)) => path.to_str().unwrap(),
             _ => &item.title,
-        };
-        let mut cmd = Command::new("xclip");
-        cmd.stdin(Stdio::piped());
-        cmd.arg("-selection").arg("clipboard");
-        let mut child = cmd.spawn()?;
-
-        if let Some(ref mut stdin) = child.stdin {
-            stdin.write(s.as_bytes())?;
         }
-        child.wait()?;
-        Ok(())
     }
 
-    #[cfg(feature="use-gtk")]
     pub fn copy_content_to_clipboard(&self, item: &Item) -> Result<(), Box<Error + Sync + Send>> {
-        let s : &str = match item.data {
-            Some(ItemData::Text(ref text)) => text,
-            Some(ItemData::Path(ref path)) => &path.to_str().unwrap(),
-            _ => &item.title,
-        };
-        let clipboard = gtk::Clipboard::get(&gdk::Atom::intern("CLIPBOARD"));
-        clipboard.set_text(s);
+        let s = Context::item_text(item);
+        // sensitive text is marked as secret, so that clipboard managers (including ours) would ignore it
+        let backend = Context::clipboard()?;
+        backend.set_text(s, item.sensitive)?;
+
+        if item.sensitive && self.clear_sensitive_after > 0 {
+            let text = s.to_string();
+            let delay = Duration::from_secs(self.clear_sensitive_after as u64);
+            thread::spawn(move || {
+                thread::sleep(delay);
+                debug!("Clearing sensitive content from clipboard, if unchanged");
+                backend.clear_if(&text);
+            });
+        }
         Ok(())
     }
 
+    /// Whether Enter pastes the item: paste enabled, and a text item without action
+    pub fn pasteable(&self, item: &Item) -> bool {
+        if paste::method().is_none() || item.action.is_some() {
+            return false;
+        }
+        match item.data {
+            Some(ItemData::Text(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Paste the item into the previously focused window, the frontend should hide itself then
+    pub fn paste(&self, item: &Item) -> Result<(), Box<Error + Sync + Send>> {
+        match paste::method() {
+            Some(PasteMethod::Type) => paste::paste(Some(Context::item_text(item).into())),
+            Some(PasteMethod::CtrlV) => {
+                self.copy_content_to_clipboard(item)?;
+                paste::paste(None)
+            },
+            None => Err(From::from("Paste is disabled")),
+        }
+    }
+
+    /// Whether the item at `idx` is appended by last filter
+    pub fn is_appended(&self, idx: usize) -> bool {
+        idx + self.appended_count >= self.list_items.len()
+    }
+
+    /// Remove items appended by last filter
+    pub fn clear_appended(&mut self) {
+        let len = self.list_items.len() - self.appended_count;
+        self.list_items.truncate(len);
+        self.appended_count = 0;
+    }
+
     /// Filter list_items using fuzzymatch
     /// return indices of list_items
-    pub fn filter(&self, pattern: &str) -> Vec<usize> {
+    /// For the root list, results from search providers (if received by
+    /// `search_callback` for `pattern`) are appended to list_items;
+    /// when nothing matches well, fallback items taking `pattern` as text
+    /// are appended and returned last
+    pub fn filter(&mut self, pattern: &str) -> Vec<usize> {
         trace!("filter: {:?}", pattern);
-        let scores = self.list_items.iter().map(|item| {
-            let search_str = if let Some(ref search_str) = item.search_str {
-                search_str
-            } else {
-                &item.title
-            };
-            fuzzymatch(search_str, pattern, false)
-        });
-        let mut indices_and_scores = (0..self.list_items.len()).zip(scores.into_iter())
-            .collect::<Vec<(usize, i32)>>();
+        self.clear_appended();
+        let mut indices_and_scores = {
+            let scores = self.list_items.iter().map(|item| {
+                let search_str = if let Some(ref search_str) = item.search_str {
+                    search_str
+                } else {
+                    &item.title
+                };
+                fuzzymatch(search_str, pattern, false)
+            });
+            (0..self.list_items.len()).zip(scores.into_iter())
+                .collect::<Vec<(usize, i32)>>()
+        };
         indices_and_scores.sort_by_key(|index_and_score| -index_and_score.1);
-        indices_and_scores.into_iter()
+        let mut best_score = indices_and_scores.first().map(|x| x.1).unwrap_or(0);
+
+        let mut indices = indices_and_scores.into_iter()
             .filter(|index_and_score| index_and_score.1 > 0)
             .map(|index_and_score| index_and_score.0)
-            .collect::<Vec<usize>>()
+            .collect::<Vec<usize>>();
+        let results = match self.search_results {
+            Some((ref query, ref results)) if self.at_root && self.reference.is_none() && query == pattern =>
+                results.clone(),
+            _ => Vec::new(),
+        };
+        if results.len() > 0 {
+            trace!("Appending {} global search results", results.len());
+            for item in results.iter() {
+                let search_str = item.search_str.as_ref().unwrap_or(&item.title);
+                best_score = cmp::max(best_score, fuzzymatch(search_str, pattern, false));
+            }
+            indices.extend(self.list_items.len() .. self.list_items.len() + results.len());
+            self.appended_count += results.len();
+            self.list_items.extend(results);
+        }
+        // scattered matches without any bonus score at most one per char
+        let weak = best_score <= pattern.chars().count() as i32;
+        if weak && pattern.trim().len() > 0 {
+            let arg = ActionArg::Text(pattern.trim().into());
+            let fallbacks = self.fallback_actions.iter()
+                .filter(|action| action.accept_arg(&arg))
+                .map(|action| {
+                    let mut item = Item::new_action_item(action.clone());
+                    item.title = format!("{}: {}", item.title, pattern.trim());
+                    item.search_str = Some(pattern.into());
+                    item.action_arg = arg.clone();
+                    item
+                })
+                .collect::<Vec<Item>>();
+            trace!("Appending {} fallback items", fallbacks.len());
+            indices.extend(self.list_items.len() .. self.list_items.len() + fallbacks.len());
+            self.appended_count += fallbacks.len();
+            self.list_items.extend(fallbacks);
+        }
+        indices
+    }
+
+    /// Search providers for `pattern` in background if in the root list,
+    /// `callback` is called with the pattern and results, to be passed to `search_callback`.
+    /// Return whether the search is started
+    pub fn search_async<F>(&self, pattern: &str, callback: F) -> bool
+    where F: FnOnce(String, Vec<Item>) + Send + 'static {
+        if !self.at_root || self.reference.is_some() || !self.global_search.accepts(pattern) {
+            return false;
+        }
+        if self.search_results.as_ref().map(|x| x.0 == pattern).unwrap_or(false) {
+            return false;
+        }
+        let query = pattern.to_string();
+        self.global_search.search_async(pattern, move |results| callback(query, results));
+        true
+    }
+
+    /// Called with results of `search_async`, return whether they are still wanted
+    pub fn search_callback(&mut self, pattern: String, results: Vec<Item>) -> bool {
+        if !self.at_root || self.reference.is_some() {
+            return false;
+        }
+        self.search_results = Some((pattern, results));
+        true
     }
 
     pub fn selectable(&self, item: &Item) -> bool {
@@ -163,22 +315,64 @@ impl Context {
     }
 
     pub fn async_select_callback(&mut self, items: Vec<Item>) {
-        self.list_items = items;
+        self.set_list_items(items);
         self.list_items.sort_by_key(|x| x.priority);
+        self.list_uuid = Some(self.running_uuid.clone());
+        self.partial_items = Vec::new();
         self.reference = None;
     }
 
-    pub fn async_select<F>(&self, item: Item, callback: F) -> String
-    where F: FnOnce(ActionResult) + Send + 'static {
-        if !self.selectable(&item) {
-            panic!("Item {} is not selectable", item);
+    /// Called with refreshed items of a cached action run,
+    /// return true if list_items are replaced, i.e. they still come from that run
+    pub fn async_refresh_callback(&mut self, uuid: &str, items: Vec<Item>) -> bool {
+        if self.list_uuid.as_ref().map(|x| x.as_str()) != Some(uuid) {
+            debug!("Refreshed items are no longer listed, ignore");
+            return false;
         }
+        self.clear_appended();
+        self.list_items = items;
+        self.list_items.sort_by_key(|x| x.priority);
+        true
+    }
+
+    /// Called with items received so far from the running action
+    pub fn async_partial_callback(&mut self, items: Vec<Item>) {
+        self.partial_items.extend(items);
+    }
+
+    /// Cancel the running action, keeping items received so far
+    pub fn cancel(&mut self) {
+        self.cancel_flag.store(true, Ordering::SeqCst);
+        if self.partial_items.len() > 0 {
+            let items = ::std::mem::replace(&mut self.partial_items, Vec::new());
+            self.async_select_callback(items);
+        }
+    }
+
+    fn spawn_action<F, P, R>(&mut self, item: Item, arg: ActionArg, force: bool,
+                             partial: P, refreshed: R, callback: F) -> String
+    where F: FnOnce(ActionResult) + Send + 'static, P: Fn(Vec<Item>) + Send + 'static,
+          R: Fn(String, Vec<Item>) + Send + 'static {
+        // only one action runs at a time
+        self.cancel_flag.store(true, Ordering::SeqCst);
+        self.cancel_flag = Arc::new(AtomicBool::new(false));
+        self.partial_items = Vec::new();
+
+        let cancel_flag = self.cancel_flag.clone();
+        let cache = self.cache.clone();
         let thread_uuid = Uuid::new_v4().simple().to_string();
+        let refreshed_uuid = thread_uuid.clone();
+        self.running_uuid = thread_uuid.clone();
         thread::Builder::new()
             .name(thread_uuid.clone())
             .spawn(move || {
                 let action = item.action.unwrap();
-                let items = action.run_arg(&item.action_arg);
+                let refreshed : Box<Fn(Vec<Item>) + Send> = Box::new(move |items: Vec<Item>| {
+                    refreshed(refreshed_uuid.clone(), items)
+                });
+                let items = ResultCache::run(&cache, &action, CacheInput::Arg(arg.clone()), force,
+                                             || action.run_arg_stream(&arg, &partial, &cancel_flag),
+                                             Some(refreshed));
                 debug!("async select complete, calling back");
                 callback(items);
             })
@@ -186,31 +380,39 @@ impl Context {
         thread_uuid
     }
 
-    pub fn async_select_with_text<F>(&self, item: Item, text: &str, callback: F) -> String
-    where F: FnOnce(ActionResult) + Send + 'static {
+    /// Run the item's action in background, `force` to bypass cached results.
+    /// `refreshed` is called with the uuid and new items, when expired results are
+    /// served and then refreshed, see `async_refresh_callback`
+    pub fn async_select<F, P, R>(&mut self, item: Item, force: bool, partial: P, refreshed: R, callback: F) -> String
+    where F: FnOnce(ActionResult) + Send + 'static, P: Fn(Vec<Item>) + Send + 'static,
+          R: Fn(String, Vec<Item>) + Send + 'static {
+        if !self.selectable(&item) {
+            panic!("Item {} is not selectable", item);
+        }
+        let arg = item.action_arg.clone();
+        self.spawn_action(item, arg, force, partial, refreshed, callback)
+    }
+
+    pub fn async_select_with_text<F, P, R>(&mut self, item: Item, text: &str, force: bool,
+                                           partial: P, refreshed: R, callback: F) -> String
+    where F: FnOnce(ActionResult) + Send + 'static, P: Fn(Vec<Item>) + Send + 'static,
+          R: Fn(String, Vec<Item>) + Send + 'static {
         if !self.selectable_with_text(&item) {
             panic!("Item {} is not selectable with text", &item);
         }
-        let text = text.to_string();
-        let thread_uuid = Uuid::new_v4().simple().to_string();
-        thread::Builder::new()
-            .name(thread_uuid.clone())
-            .spawn(move || {
-                let action = item.action.unwrap();
-                let items = action.run_text(&text);
-                debug!("async select with text complete, calling back");
-                callback(items);
-            })
-            .unwrap();
-        thread_uuid
+        self.spawn_action(item, ActionArg::Text(text.to_string()), force, partial, refreshed, callback)
     }
 
-    pub fn select(&mut self, item: Item) -> Result<(), Box<Error + Send + Sync>> {
+    /// Run the item's action, `force` to bypass cached results
+    pub fn select(&mut self, item: Item, force: bool) -> Result<(), Box<Error + Send + Sync>> {
         if !self.selectable(&item) {
             panic!("Item {} is not selectable", item);
         }
         if let Some(ref action) = item.action {
-            self.list_items = action.run_arg(&item.action_arg)?;
+            let input = CacheInput::Arg(item.action_arg.clone());
+            let items = ResultCache::run(&self.cache, action, input, force,
+                                         || action.run_arg(&item.action_arg), None)?;
+            self.set_list_items(items);
             self.list_items.sort_by_key(|item| item.priority );
         } else {
             panic!("Should not reach here");
@@ -220,12 +422,16 @@ impl Context {
         Ok(())
     }
 
-    pub fn select_with_text(&mut self, item: Item, text: &str) -> Result<(), Box<Error + Send + Sync>> {
+    /// Run the item's action with `text`, `force` to bypass cached results
+    pub fn select_with_text(&mut self, item: Item, text: &str, force: bool) -> Result<(), Box<Error + Send + Sync>> {
         if !self.selectable_with_text(&item) {
             panic!("Item {} is not selectable with text", &item);
         }
         if let Some(ref action) = item.action {
-            self.list_items = action.run_text(text)?;
+            let input = CacheInput::Arg(ActionArg::Text(text.into()));
+            let items = ResultCache::run(&self.cache, action, input, force,
+                                         || action.run_text(text), None)?;
+            self.set_list_items(items);
             self.list_items.sort_by_key(|item| item.priority );
         } else {
             panic!("Should not reach here");
@@ -243,30 +449,55 @@ impl Context {
         if !self.quicksend_able(&item) {
             panic!("Item {} is not quicksend_able", item);
         }
+        // text of existing path is sent as path
+        if let Some(ItemData::Text(ref text)) = item.data {
+            if content::classify(text) == ContentKind::Path {
+                let path = actions::expand_path(text.trim());
+                if path.exists() {
+                    let mut path_item = Item::new(text);
+                    path_item.data = Some(ItemData::Path(path));
+                    return self.quicksend(path_item);
+                }
+            }
+        }
         if let Some(ref data) = item.data {
-            self.list_items = match data {
+            let items = match data {
                 &ItemData::Text(ref text) => {
-                    self.all_actions.iter()
-                    .filter(|action| action.accept_text())
+                    // actions that suit this kind of content go first
+                    let kind = content::classify(text);
+                    debug!("Quicksend text of kind {:?}", kind);
+                    let mut items = self.all_actions.iter()
+                    .filter(|action| action.accept_arg(&ActionArg::Text(text.clone())))
                     .map(|action| {
+                        let support = action.content_support(kind);
                         let mut item = Item::new_action_item(action.clone());
                         item.action_arg = ActionArg::Text(text.clone());
-                        item
+                        (support, item)
                     })
-                    .collect()
+                    .collect::<Vec<(ContentSupport, Item)>>();
+                    items.sort_by_key(|x| (x.0, x.1.priority));
+                    items.into_iter().map(|x| x.1).collect()
                 },
                 &ItemData::Path(ref path) => {
-                    self.all_actions.iter()
-                    .filter(|action| action.accept_path())
+                    // applications that handle this type of file go first
+                    let path_mime = mime::guess(path);
+                    let mut items = self.all_actions.iter()
+                    .filter(|action| action.accept_arg(&ActionArg::Path(path.clone())))
                     .map(|action| {
+                        let support = action.mime_support(&path_mime);
                         let mut item = Item::new_action_item(action.clone());
                         item.action_arg = ActionArg::Path(path.clone());
-                        item
+                        if support == MimeSupport::Default {
+                            item.badge = Some("Default".into());
+                        }
+                        (support, item)
                     })
-                    .collect()
+                    .collect::<Vec<(MimeSupport, Item)>>();
+                    items.sort_by_key(|x| (x.0, x.1.priority));
+                    items.into_iter().map(|x| x.1).collect()
                 },
             };
-            self.list_items.sort_by_key(|item| item.priority );
+            self.set_list_items(items);
             self.reference = Some(data.clone());
         } else {
             panic!("Should not reach here");
@@ -276,7 +507,7 @@ impl Context {
 
     pub fn back(&mut self) -> Result<(), Box<Error + Send + Sync>> {
         if let Some(action_item) = self.history_items.pop() {
-            self.select(action_item)
+            self.select(action_item, false)
         } else {
             self.reset();
             Ok(())
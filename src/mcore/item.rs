@@ -44,6 +44,9 @@ pub struct Item {
     /// Search str, fallback to title
     pub search_str: Option<String>,
 
+    /// Data is secret (like password), should not be kept in clipboard
+    pub sensitive: bool,
+
     /// Action, optional
     pub action: Option<Arc<Box<Action + Sync + Send>>>,
     /// Argument for action, optional
@@ -76,6 +79,7 @@ impl Item {
             priority: 0,
             data: None,
             search_str: None,
+            sensitive: false,
             action: None,
             action_arg: ActionArg::None,
         }
@@ -0,0 +1,270 @@
+//! MIME type detection using the shared-mime-info database, and `file` for content
+
+extern crate glob;
+
+use std::fs::File;
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use mcore::xdg;
+
+struct GlobRule {
+    weight: i32,
+    mime: String,
+    pattern: glob::Pattern,
+    pattern_len: usize,
+    case_sensitive: bool,
+}
+
+lazy_static! {
+    static ref GLOBS: Vec<GlobRule> = load_globs();
+}
+
+/// Parse `globs2` file, each line is `weight:mime/type:glob[:flags]`
+fn load_globs_file(path: &Path, rules: &mut Vec<GlobRule>) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return,
+    };
+    debug!("Loading MIME globs from {:?}", path);
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.splitn(4, ':').collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        let weight = match parts[0].parse::<i32>() {
+            Ok(weight) => weight,
+            Err(_) => continue,
+        };
+        let case_sensitive = parts.len() > 3 && parts[3].split(',').any(|x| x == "cs");
+        let pattern_str = if case_sensitive { parts[2].to_string() } else { parts[2].to_lowercase() };
+        if let Ok(pattern) = glob::Pattern::new(&pattern_str) {
+            rules.push(GlobRule {
+                weight: weight,
+                mime: parts[1].into(),
+                pattern: pattern,
+                pattern_len: pattern_str.len(),
+                case_sensitive: case_sensitive,
+            });
+        }
+    }
+}
+
+fn load_globs() -> Vec<GlobRule> {
+    let mut ret = Vec::new();
+    for dir in xdg::data_dirs() {
+        load_globs_file(&dir.join("mime/globs2"), &mut ret);
+    }
+    if ret.len() == 0 {
+        warn!("No shared-mime-info database found");
+    }
+    ret
+}
+
+/// Guess MIME type of file name using glob rules
+pub fn guess_from_name(name: &str) -> Option<String> {
+    let name_lowercase = name.to_lowercase();
+    let mut best: Option<&GlobRule> = None;
+    for rule in GLOBS.iter() {
+        let matched = if rule.case_sensitive {
+            rule.pattern.matches(name)
+        } else {
+            rule.pattern.matches(&name_lowercase)
+        };
+        if !matched {
+            continue;
+        }
+        best = match best {
+            Some(b) if (b.weight, b.pattern_len) >= (rule.weight, rule.pattern_len) => Some(b),
+            _ => Some(rule),
+        };
+    }
+    best.map(|x| x.mime.clone())
+}
+
+/// Guess MIME type of file content using `file`, which reads the magic database
+fn guess_from_magic(path: &Path) -> Option<String> {
+    let output = Command::new("file")
+        .arg("--brief").arg("--mime-type").arg("--").arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(ref output) if output.status.success() => output,
+        Ok(_) => return None,
+        Err(ref error) => {
+            debug!("Unable to run file for {:?}: {}", path, error);
+            return None;
+        },
+    };
+    let mime = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // unknown binary content is left to the fallback below
+    if mime.contains('/') && mime != "application/octet-stream" {
+        Some(mime)
+    } else {
+        None
+    }
+}
+
+/// Guess MIME type of file, by its name first, then by its content
+pub fn guess(path: &Path) -> String {
+    if path.is_dir() {
+        return "inode/directory".into();
+    }
+    if let Some(name) = path.file_name() {
+        if let Some(mime) = guess_from_name(&name.to_string_lossy()) {
+            return mime;
+        }
+    }
+    let mut buf = [0u8; 512];
+    let len = match File::open(path) {
+        Ok(mut file) => file.read(&mut buf).unwrap_or(0),
+        Err(_) => return "application/octet-stream".into(),
+    };
+    if len == 0 {
+        return "application/x-zerosize".into();
+    }
+    if let Some(mime) = guess_from_magic(path) {
+        return mime;
+    }
+    if buf[..len].contains(&0) {
+        "application/octet-stream".into()
+    } else {
+        "text/plain".into()
+    }
+}
+
+/// Whether `mime` matches `pattern`, which can be like `image/*`
+pub fn matches(pattern: &str, mime: &str) -> bool {
+    if pattern == "*" || pattern == "*/*" || pattern == mime {
+        return true;
+    }
+    if pattern.ends_with("/*") {
+        let prefix = &pattern[..pattern.len() - 1];
+        return mime.starts_with(prefix);
+    }
+    false
+}
+
+lazy_static! {
+    static ref SUBCLASSES: HashMap<String, Vec<String>> = load_subclasses();
+    static ref DEFAULT_APPLICATIONS: HashMap<String, Vec<String>> = load_default_applications();
+}
+
+/// Parse `subclasses` file, each line is `mime/type parent/type`
+fn load_subclasses() -> HashMap<String, Vec<String>> {
+    let mut ret : HashMap<String, Vec<String>> = HashMap::new();
+    for dir in xdg::data_dirs() {
+        let file = match File::open(dir.join("mime/subclasses")) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        for line in BufReader::new(file).lines() {
+            if let Ok(line) = line {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                if parts.len() == 2 {
+                    ret.entry(parts[0].into()).or_insert(Vec::new()).push(parts[1].into());
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// MIME type and all its parent types
+pub fn ancestors(mime: &str) -> Vec<String> {
+    let mut ret = vec![mime.to_string()];
+    let mut idx = 0;
+    while idx < ret.len() {
+        if let Some(parents) = SUBCLASSES.get(&ret[idx]) {
+            for parent in parents {
+                if !ret.contains(parent) {
+                    ret.push(parent.clone());
+                }
+            }
+        }
+        idx += 1;
+    }
+    // every text type is a subclass of text/plain
+    if mime.starts_with("text/") && !ret.iter().any(|x| x == "text/plain") {
+        ret.push("text/plain".into());
+    }
+    ret
+}
+
+/// Parse `[Default Applications]` sections of all mimeapps.list,
+/// more important files first
+fn load_default_applications() -> HashMap<String, Vec<String>> {
+    let mut files = Vec::new();
+    for dir in xdg::config_dirs() {
+        files.push(dir.join("mimeapps.list"));
+    }
+    for dir in xdg::data_dirs() {
+        files.push(dir.join("applications/mimeapps.list"));
+    }
+
+    let mut ret : HashMap<String, Vec<String>> = HashMap::new();
+    for path in files {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        debug!("Loading default applications from {:?}", path);
+        let mut in_section = false;
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_section = line == "[Default Applications]";
+                continue;
+            }
+            if !in_section {
+                continue;
+            }
+            let parts: Vec<&str> = line.splitn(2, '=').collect();
+            if parts.len() < 2 {
+                continue;
+            }
+            let apps = ret.entry(parts[0].trim().into()).or_insert(Vec::new());
+            for app in parts[1].split(';') {
+                let app = app.trim();
+                if app.len() > 0 && !apps.iter().any(|x| x == app) {
+                    apps.push(app.into());
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Default applications (desktop file IDs, like `firefox.desktop`) for MIME type,
+/// as defined in mimeapps.list, most preferred first
+pub fn default_applications(mime: &str) -> Vec<String> {
+    for m in ancestors(mime) {
+        if let Some(apps) = DEFAULT_APPLICATIONS.get(&m) {
+            return apps.clone();
+        }
+    }
+    Vec::new()
+}
+
+/// The default application for MIME type, i.e. the first one in `default_applications`
+/// whose desktop file is installed
+pub fn default_application(mime: &str) -> Option<String> {
+    let data_dirs = xdg::data_dirs();
+    default_applications(mime).into_iter()
+        .find(|app| data_dirs.iter().any(|dir| dir.join("applications").join(app).is_file()))
+}
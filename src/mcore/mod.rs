@@ -8,4 +8,9 @@
 pub mod action;
 pub mod item;
 pub mod fuzzymatch;
+pub mod xdg;
+pub mod mime;
+pub mod content;
+pub mod search;
+pub mod cache;
 pub mod context;
@@ -0,0 +1,342 @@
+//! Global search from the root query, blending children of search providers
+
+use toml;
+
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+
+use mcore::action::{Action, ActionArg, ActionResult};
+use mcore::item::{Item, ItemData};
+use mcore::fuzzymatch::fuzzymatch;
+use mcore::cache::{ResultCache, CacheInput};
+
+#[derive(Deserialize, Clone)]
+struct ProviderConfig {
+    limit: Option<usize>,
+    /// Scores are multiplied by this, 0 to disable
+    weight: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    enabled: bool,
+    min_query_length: usize,
+    /// Milliseconds to wait for providers
+    timeout: u64,
+    /// Results per provider
+    limit: usize,
+    /// By provider name (title of the action)
+    providers: Option<HashMap<String, ProviderConfig>>,
+}
+
+/// Wait for more keystrokes before searching in background, in milliseconds
+const DEBOUNCE_TIME: u64 = 100;
+
+struct Provider {
+    action: Arc<Box<Action + Sync + Send>>,
+    name: String,
+    limit: usize,
+    weight: f32,
+    /// Results of a provider not using the query, with the session they were fetched in.
+    /// Kept in memory only, so that sensitive ones are listed once per session as well
+    listing: Mutex<Option<(usize, Vec<Item>)>>,
+}
+
+impl Provider {
+    /// Results to be filtered by `query`, listed once per `session` if not using the query
+    fn results(&self, cache: &Arc<ResultCache>, query: &str, session: usize) -> ActionResult {
+        let action = &self.action;
+        if action.search_uses_query() {
+            let input = CacheInput::Search(query.to_string());
+            return ResultCache::run(cache, action, input, false, || action.search(query), None);
+        }
+        // held while listing, so that concurrent searches wait instead of listing again
+        let mut listing = self.listing.lock().unwrap();
+        if let Some((ref listed_session, ref items)) = *listing {
+            if *listed_session == session {
+                return Ok(items.clone());
+            }
+        }
+        let items = ResultCache::run(cache, action, CacheInput::Arg(ActionArg::None), false,
+                                     || action.search(query), None)?;
+        *listing = Some((session, items.clone()));
+        Ok(items)
+    }
+}
+
+#[derive(Clone)]
+pub struct GlobalSearch {
+    providers: Arc<Vec<Provider>>,
+    min_query_length: usize,
+    timeout: Duration,
+    /// Provider results are cached by query, or once if not using it
+    cache: Arc<ResultCache>,
+    /// Increased by each background search, older ones are dropped
+    generation: Arc<AtomicUsize>,
+    /// Increased by `reset`, listings of providers are fetched again
+    session: Arc<AtomicUsize>,
+}
+
+/// Key for de-duplication, the title and the content
+fn dedup_key(item: &Item) -> String {
+    let data = match item.data {
+        Some(ItemData::Text(ref text)) => text.clone(),
+        Some(ItemData::Path(ref path)) => path.to_string_lossy().into_owned(),
+        None => String::new(),
+    };
+    format!("{}\0{}", item.title, data)
+}
+
+/// Score of item against query, zero if not matching
+fn score(item: &Item, query: &str) -> i32 {
+    let search_str = match item.search_str {
+        Some(ref search_str) => search_str,
+        None => &item.title,
+    };
+    fuzzymatch(search_str, query, false)
+}
+
+impl GlobalSearch {
+    /// Providers are taken from `actions`, disabled if `config` is None
+    pub fn new(config: Option<toml::Value>, actions: &[Arc<Box<Action + Sync + Send>>],
+               cache: Arc<ResultCache>) -> GlobalSearch {
+        let mut ret = GlobalSearch {
+            providers: Arc::new(Vec::new()),
+            min_query_length: 0,
+            timeout: Duration::from_millis(0),
+            cache: cache,
+            generation: Arc::new(AtomicUsize::new(0)),
+            session: Arc::new(AtomicUsize::new(0)),
+        };
+        let config = match config.map(|x| x.try_into::<Config>()) {
+            Some(Ok(config)) => config,
+            Some(Err(error)) => {
+                warn!("Error loading global search config: {}", error);
+                return ret;
+            },
+            None => return ret,
+        };
+        if !config.enabled {
+            return ret;
+        }
+        let provider_configs = config.providers.unwrap_or(HashMap::new());
+        let mut providers = Vec::new();
+        for action in actions.iter().filter(|x| x.is_search_provider()) {
+            let name = action.get_item().title;
+            let provider_config = provider_configs.get(&name).cloned()
+                .unwrap_or(ProviderConfig { limit: None, weight: None });
+            let weight = provider_config.weight.unwrap_or(1.0);
+            if weight <= 0.0 {
+                debug!("Search provider {} disabled", name);
+                continue;
+            }
+            debug!("Search provider: {}", name);
+            providers.push(Provider {
+                action: action.clone(),
+                name: name,
+                limit: provider_config.limit.unwrap_or(config.limit),
+                weight: weight,
+                listing: Mutex::new(None),
+            });
+        }
+        ret.providers = Arc::new(providers);
+        ret.min_query_length = config.min_query_length;
+        ret.timeout = Duration::from_millis(config.timeout);
+        ret
+    }
+
+    /// Whether `query` would be searched at all
+    pub fn accepts(&self, query: &str) -> bool {
+        self.providers.len() > 0 && query.chars().count() >= self.min_query_length
+    }
+
+    /// Start a new session, e.g. when the launcher is shown again
+    pub fn reset(&self) {
+        self.session.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Search in a background thread after a short delay, `callback` is called
+    /// with the results unless another search is started meanwhile
+    pub fn search_async<F>(&self, query: &str, callback: F)
+    where F: FnOnce(Vec<Item>) + Send + 'static {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let search = self.clone();
+        let query = query.to_string();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(DEBOUNCE_TIME));
+            if search.generation.load(Ordering::SeqCst) != generation {
+                trace!("Global search for {:?} is outdated before started", query);
+                return;
+            }
+            let results = search.search(&query);
+            if search.generation.load(Ordering::SeqCst) != generation {
+                trace!("Global search for {:?} is outdated, dropped", query);
+                return;
+            }
+            callback(results);
+        });
+    }
+
+    /// Search all providers in parallel, waiting for them until timeout.
+    /// Results are grouped by provider, better groups first, duplicates removed
+    pub fn search(&self, query: &str) -> Vec<Item> {
+        if !self.accepts(query) {
+            return Vec::new();
+        }
+        let (send_ch, recv_ch) = mpsc::channel::<(usize, ActionResult)>();
+        let session = self.session.load(Ordering::SeqCst);
+        for idx in 0..self.providers.len() {
+            let providers = self.providers.clone();
+            let query = query.to_string();
+            let send_ch = send_ch.clone();
+            let cache = self.cache.clone();
+            thread::spawn(move || {
+                let result = providers[idx].results(&cache, &query, session);
+                let _ = send_ch.send((idx, result));
+            });
+        }
+        drop(send_ch);
+
+        // (best weighted score, provider index, scored items)
+        let mut groups : Vec<(i32, usize, Vec<(i32, Item)>)> = Vec::new();
+        let deadline = Instant::now() + self.timeout;
+        let mut responded = 0;
+        for _ in 0..self.providers.len() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let (idx, result) = match recv_ch.recv_timeout(deadline - now) {
+                Ok(x) => x,
+                Err(_) => break,
+            };
+            responded += 1;
+            let provider = &self.providers[idx];
+            let items = match result {
+                Ok(items) => items,
+                Err(error) => {
+                    debug!("Search provider {} failed: {}", provider.name, error);
+                    continue;
+                },
+            };
+            let mut scored : Vec<(i32, Item)> = items.into_iter()
+                .map(|item| ((score(&item, query) as f32 * provider.weight) as i32, item))
+                .filter(|x| x.0 > 0)
+                .collect();
+            scored.sort_by_key(|x| -x.0);
+            scored.truncate(provider.limit);
+            if let Some(best) = scored.first().map(|x| x.0) {
+                groups.push((best, idx, scored));
+            }
+        }
+        if responded < self.providers.len() {
+            debug!("Global search: {} of {} providers responded in time", responded, self.providers.len());
+        }
+        groups.sort_by_key(|x| (-x.0, x.1));
+
+        let mut seen = HashSet::new();
+        let mut ret = Vec::new();
+        for (_, idx, scored) in groups.into_iter() {
+            let name = &self.providers[idx].name;
+            for (_, mut item) in scored.into_iter() {
+                if !seen.insert(dedup_key(&item)) {
+                    continue;
+                }
+                item.badge = Some(match item.badge.take() {
+                    Some(badge) => format!("{}: {}", name, badge),
+                    None => name.clone(),
+                });
+                ret.push(item);
+            }
+        }
+        ret
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use toml;
+    use std::sync::{Arc, Mutex};
+    use mcore::action::{Action, ActionResult};
+    use mcore::item::Item;
+    use mcore::search::GlobalSearch;
+    use mcore::cache::ResultCache;
+
+    struct TestProvider {
+        name: &'static str,
+        titles: Vec<&'static str>,
+    }
+
+    impl Action for TestProvider {
+        fn get_item(&self) -> Item { Item::new(self.name) }
+        fn is_search_provider(&self) -> bool { true }
+        fn run(&self) -> ActionResult {
+            Ok(self.titles.iter().map(|x| Item::new_text_item(x)).collect())
+        }
+    }
+
+    #[test]
+    fn global_search_test() {
+        let config = r#"
+            enabled = true
+            min_query_length = 2
+            timeout = 1000
+            limit = 2
+            [providers]
+            "Emojis" = { weight = 4.0 }
+            "Disabled" = { weight = 0.0 }
+        "#.parse::<toml::Value>().unwrap();
+        let actions : Vec<Arc<Box<Action + Sync + Send>>> = vec![
+            Arc::new(Box::new(TestProvider { name: "History", titles: vec!["smile", "smiley cat", "small", "other"] })),
+            Arc::new(Box::new(TestProvider { name: "Emojis", titles: vec!["smile", "frown"] })),
+            Arc::new(Box::new(TestProvider { name: "Disabled", titles: vec!["smile"] })),
+        ];
+        let search = GlobalSearch::new(Some(config), &actions, Arc::new(ResultCache::new(None)));
+        assert_eq!(search.search("s").len(), 0);
+
+        let results = search.search("smil");
+        let titles : Vec<&str> = results.iter().map(|x| x.title.as_str()).collect();
+        let badges : Vec<&str> = results.iter().map(|x| x.badge.as_ref().unwrap().as_str()).collect();
+        // weighted provider first, duplicated "smile" dropped, limited to 2 per provider
+        assert_eq!(titles, vec!["smile", "smiley cat"]);
+        assert_eq!(badges, vec!["Emojis", "History"]);
+    }
+
+    struct SensitiveProvider {
+        runs: Arc<AtomicUsize>,
+    }
+
+    impl Action for SensitiveProvider {
+        fn get_item(&self) -> Item { Item::new("Secrets") }
+        fn is_search_provider(&self) -> bool { true }
+        fn run(&self) -> ActionResult {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            let mut item = Item::new_text_item("secret");
+            item.sensitive = true;
+            Ok(vec![item])
+        }
+    }
+
+    #[test]
+    fn listing_once_per_session_test() {
+        let config = "enabled = true\nmin_query_length = 1\ntimeout = 1000\nlimit = 5".parse::<toml::Value>().unwrap();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let actions : Vec<Arc<Box<Action + Sync + Send>>> = vec![
+            Arc::new(Box::new(SensitiveProvider { runs: runs.clone() })),
+        ];
+        let search = GlobalSearch::new(Some(config), &actions, Arc::new(ResultCache::new(None)));
+        assert_eq!(search.search("s").len(), 1);
+        assert_eq!(search.search("se").len(), 1);
+        assert_eq!(search.search("x").len(), 0);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        search.reset();
+        assert_eq!(search.search("sec").len(), 1);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,56 @@
+//! XDG base directories
+
+use std::env;
+use std::path::PathBuf;
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    match env::var(name) {
+        Ok(ref value) if value.starts_with('/') => Some(PathBuf::from(value)),
+        _ => None,
+    }
+}
+
+fn home_dir() -> PathBuf {
+    env::home_dir().unwrap_or(PathBuf::from("/"))
+}
+
+/// $XDG_DATA_HOME, default to ~/.local/share
+pub fn data_home() -> PathBuf {
+    env_path("XDG_DATA_HOME").unwrap_or(home_dir().join(".local/share"))
+}
+
+/// $XDG_CONFIG_HOME, default to ~/.config
+pub fn config_home() -> PathBuf {
+    env_path("XDG_CONFIG_HOME").unwrap_or(home_dir().join(".config"))
+}
+
+/// $XDG_RUNTIME_DIR, default to /tmp
+pub fn runtime_dir() -> PathBuf {
+    env_path("XDG_RUNTIME_DIR").unwrap_or(PathBuf::from("/tmp"))
+}
+
+/// Data directories, most important first: $XDG_DATA_HOME, then $XDG_DATA_DIRS
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut ret = vec![data_home()];
+    let dirs = env::var("XDG_DATA_DIRS").unwrap_or(String::new());
+    let dirs = if dirs.len() == 0 { "/usr/local/share:/usr/share".into() } else { dirs };
+    for dir in dirs.split(':') {
+        if dir.starts_with('/') {
+            ret.push(PathBuf::from(dir));
+        }
+    }
+    ret
+}
+
+/// Config directories, most important first: $XDG_CONFIG_HOME, then $XDG_CONFIG_DIRS
+pub fn config_dirs() -> Vec<PathBuf> {
+    let mut ret = vec![config_home()];
+    let dirs = env::var("XDG_CONFIG_DIRS").unwrap_or(String::new());
+    let dirs = if dirs.len() == 0 { "/etc/xdg".into() } else { dirs };
+    for dir in dirs.split(':') {
+        if dir.starts_with('/') {
+            ret.push(PathBuf::from(dir));
+        }
+    }
+    ret
+}